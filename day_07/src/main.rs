@@ -8,29 +8,129 @@ use nom::{
     sequence::terminated,
     Finish, IResult,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
 use std::fmt;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use std::str;
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
     let rules = RuleSet::read_from(&input)?;
     // rules.print();
-    part1(&rules);
-    part2(&rules);
+
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            part1(&rules)?;
+            part2(&rules)?;
+        }
+        Some("query") => {
+            print!("{}", Query::from_args(&args[2..])?.run(&rules)?);
+        }
+        Some("export") => {
+            let around = match args.get(3).map(|s| s.as_str()) {
+                None => None,
+                Some("--around") => Some(Color::from(
+                    args.get(4)
+                        .with_context(|| "--around requires a color")?
+                        .as_str(),
+                )),
+                Some(arg) => bail!("Unknown argument: {}", arg),
+            };
+            match args.get(2).map(|s| s.as_str()) {
+                Some("dot") => print!("{}", rules.to_dot(around.as_ref())?),
+                Some("graphml") => print!("{}", rules.to_graphml(around.as_ref())?),
+                _ => bail!("Usage: export <dot|graphml> [--around <color>]"),
+            }
+        }
+        Some(cmd) => bail!("Unknown command: {}", cmd),
+    }
 
     Ok(())
 }
 
+/// Ad hoc questions about a rule file, colors are given as two words each.
+#[derive(Debug, PartialEq)]
+enum Query {
+    Containers(Color),
+    Count(Color),
+    Paths(Color, Color),
+    Roots,
+    Depth(Option<Color>),
+}
+
+impl Query {
+    /// Usage: `query <containers <color>|count <color>|paths <from> <to>|roots|depth [color]>`
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let cmd = args.first().with_context(|| "No query provided!")?;
+        let words: Vec<&str> = args[1..]
+            .iter()
+            .flat_map(|a| a.split_whitespace())
+            .collect();
+        if !words.len().is_multiple_of(2) {
+            bail!("Colors consist of two words, got: {}", words.join(" "));
+        }
+        let mut colors = words
+            .chunks(2)
+            .map(|w| Color::from(w.join(" ").as_str()))
+            .collect::<Vec<_>>()
+            .into_iter();
+
+        let query = match (cmd.as_str(), colors.len()) {
+            ("containers", 1) => Query::Containers(colors.next().unwrap()),
+            ("count", 1) => Query::Count(colors.next().unwrap()),
+            ("paths", 2) => Query::Paths(colors.next().unwrap(), colors.next().unwrap()),
+            ("roots", 0) => Query::Roots,
+            ("depth", 0) | ("depth", 1) => Query::Depth(colors.next()),
+            (cmd, num) => bail!("Invalid query {} with {} color(s)", cmd, num),
+        };
+        Ok(query)
+    }
+
+    pub fn run(&self, rules: &RuleSet) -> Result<String> {
+        let mut rv = String::new();
+        match self {
+            Query::Containers(color) => {
+                let mut containers: Vec<_> = rules.contain(color)?.into_iter().collect();
+                containers.sort_by(|l, r| l.name.cmp(&r.name));
+                for c in containers {
+                    rv.push_str(&format!("{}\n", c));
+                }
+            }
+            Query::Count(color) => {
+                rv.push_str(&format!("{}\n", rules.count(color)?));
+            }
+            Query::Paths(from, to) => {
+                let paths = rules.paths(from, to)?;
+                let mut total = 0usize;
+                for (path, multiplicity) in paths.iter() {
+                    let path: Vec<_> = path.iter().map(|c| c.to_string()).collect();
+                    rv.push_str(&format!("{} (x{})\n", path.join(" -> "), multiplicity));
+                    total = total
+                        .checked_add(*multiplicity)
+                        .with_context(|| "Path multiplicity overflow")?;
+                }
+                rv.push_str(&format!(
+                    "{} path(s), {} bag(s) in total\n",
+                    paths.len(),
+                    total
+                ));
+            }
+            Query::Roots => {
+                for c in rules.roots() {
+                    rv.push_str(&format!("{}\n", c));
+                }
+            }
+            Query::Depth(color) => {
+                rv.push_str(&format!("{}\n", rules.depth(color.as_ref())?));
+            }
+        }
+        Ok(rv)
+    }
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct Color {
     name: String,
@@ -96,7 +196,7 @@ impl Bags {
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct BagRule {
     container: Color,
-    contents: HashMap<Color, Bags>,
+    contents: Vec<Bags>,
 }
 
 impl BagRule {
@@ -105,8 +205,6 @@ impl BagRule {
         let (i, _) = tag(" bags contain ")(i)?;
         let (i, contents) = Bags::parse(i)?;
 
-        let contents: HashMap<_, _> = contents.into_iter().map(|b| (b.color.clone(), b)).collect();
-
         Ok((
             i,
             Self {
@@ -115,82 +213,504 @@ impl BagRule {
             },
         ))
     }
+}
 
-    pub fn contains(&self, color: &Color) -> bool {
-        self.contents.contains_key(color)
-    }
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done,
 }
 
+/// Bag rules as a graph: every color is a node, edges point from a bag to its contents.
 struct RuleSet {
-    rules: HashMap<Color, BagRule>,
+    colors: Vec<Color>,
+    index: HashMap<Color, usize>,
+    // contents[i]: (bag, count) directly inside bag i
+    contents: Vec<Vec<(usize, usize)>>,
+    // containers[i]: bags directly containing bag i
+    containers: Vec<Vec<usize>>,
 }
 
 impl RuleSet {
     pub fn read_from(path: &Path) -> Result<Self> {
-        let mut rules = HashMap::new();
-        for line in io::BufReader::new(File::open(&path)?).lines() {
-            let line = line?;
-            let parsed = BagRule::parse(&line).finish();
+        Self::parse(&read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for line in content.lines() {
+            let parsed = BagRule::parse(line).finish();
             match parsed {
                 Ok((_, br)) => {
-                    rules.insert(br.container.clone(), br);
+                    rules.push(br);
                 }
                 Err(e) => bail!("Error parsing {}: {}", line, e),
             }
         }
-        Ok(Self { rules })
+        Self::from_rules(rules)
+    }
+
+    pub fn from_rules(rules: Vec<BagRule>) -> Result<Self> {
+        let mut rs = Self {
+            colors: Vec::new(),
+            index: HashMap::new(),
+            contents: Vec::new(),
+            containers: Vec::new(),
+        };
+        let mut has_rule = HashSet::new();
+
+        for rule in rules {
+            let container = rs.insert(&rule.container);
+            if !has_rule.insert(container) {
+                bail!("Duplicate rule for {} bags.", rule.container);
+            }
+            for bags in rule.contents.iter() {
+                let inner = rs.insert(&bags.color);
+                rs.contents[container].push((inner, bags.count));
+                rs.containers[inner].push(container);
+            }
+        }
+        Ok(rs)
+    }
+
+    fn insert(&mut self, color: &Color) -> usize {
+        if let Some(idx) = self.index.get(color) {
+            return *idx;
+        }
+        let idx = self.colors.len();
+        self.colors.push(color.clone());
+        self.index.insert(color.clone(), idx);
+        self.contents.push(Vec::new());
+        self.containers.push(Vec::new());
+        idx
+    }
+
+    fn id(&self, color: &Color) -> Result<usize> {
+        self.index
+            .get(color)
+            .cloned()
+            .with_context(|| format!("Unknown color: {}", color))
     }
 
     /// Return a HashSet of bag colors the given color is in (recursively).
-    pub fn contain(&self, color: &Color) -> HashSet<Color> {
+    pub fn contain(&self, color: &Color) -> Result<HashSet<Color>> {
+        Ok(self
+            .ancestors(self.id(color)?)
+            .into_iter()
+            .map(|idx| self.colors[idx].clone())
+            .collect())
+    }
+
+    pub fn count(&self, color: &Color) -> Result<usize> {
+        let start = self.id(color)?;
+        let mut memo: Vec<usize> = vec![0; self.colors.len()];
+
+        for node in self.post_order(&[start])? {
+            memo[node] = self.contents[node]
+                .iter()
+                .try_fold(0usize, |acc, (inner, count)| {
+                    acc.checked_add(count.checked_mul(memo[*inner].checked_add(1)?)?)
+                })
+                .with_context(|| format!("Bag count overflow in {}", self.colors[node]))?;
+        }
+        Ok(memo[start])
+    }
+
+    /// Bags not contained in any other bag.
+    pub fn roots(&self) -> Vec<Color> {
+        let mut roots: Vec<_> = self
+            .containers
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_empty())
+            .map(|(idx, _)| self.colors[idx].clone())
+            .collect();
+        roots.sort_by(|l, r| l.name.cmp(&r.name));
+        roots
+    }
+
+    /// Maximum number of levels of bags inside the given color, or inside any bag if none given.
+    pub fn depth(&self, color: Option<&Color>) -> Result<usize> {
+        let starts = match color {
+            Some(color) => vec![self.id(color)?],
+            None => (0..self.colors.len()).collect(),
+        };
+        let mut depth = vec![0; self.colors.len()];
+
+        for node in self.post_order(&starts)? {
+            depth[node] = self.contents[node]
+                .iter()
+                .map(|(inner, _)| depth[*inner] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        Ok(starts.iter().map(|s| depth[*s]).max().unwrap_or(0))
+    }
+
+    /// All paths from `from` down to `to`, each with the number of `to` bags it accounts for.
+    pub fn paths(&self, from: &Color, to: &Color) -> Result<Vec<(Vec<Color>, usize)>> {
+        let start = self.id(from)?;
+        let target = self.id(to)?;
+        // paths are only finite without cycles
+        self.post_order(&[start])?;
+
+        let mut leads_to_target = self.ancestors(target);
+        leads_to_target.insert(target);
+
+        let mut paths = Vec::new();
+        // (bag, index of next content to visit, multiplicity so far)
+        let mut stack = vec![(start, 0, 1usize)];
+
+        while let Some(&(node, child, multiplicity)) = stack.last() {
+            if node == target && stack.len() > 1 {
+                paths.push((
+                    stack
+                        .iter()
+                        .map(|(n, _, _)| self.colors[*n].clone())
+                        .collect(),
+                    multiplicity,
+                ));
+                stack.pop();
+                continue;
+            }
+            match self.contents[node].get(child) {
+                Some(&(next, count)) => {
+                    if let Some(top) = stack.last_mut() {
+                        top.1 += 1;
+                    }
+                    if leads_to_target.contains(&next) {
+                        let multiplicity = multiplicity
+                            .checked_mul(count)
+                            .with_context(|| "Path multiplicity overflow")?;
+                        stack.push((next, 0, multiplicity));
+                    }
+                }
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    /// All bags (transitively) containing the given bag.
+    fn ancestors(&self, start: usize) -> HashSet<usize> {
+        self.reachable(start, &self.containers)
+    }
+
+    /// All bags (transitively) inside the given bag.
+    fn descendants(&self, start: usize) -> HashSet<usize> {
+        let contents: Vec<Vec<usize>> = self
+            .contents
+            .iter()
+            .map(|c| c.iter().map(|(inner, _)| *inner).collect())
+            .collect();
+        self.reachable(start, &contents)
+    }
+
+    fn reachable(&self, start: usize, edges: &[Vec<usize>]) -> HashSet<usize> {
         let mut found = HashSet::new();
-        let mut to_check = vec![color.clone()];
-
-        while to_check.len() > 0 {
-            let current = to_check.pop().unwrap();
-            let containers = self.is_in(&current);
-            for color in containers {
-                if !found.contains(&color) {
-                    found.insert(color.clone());
-                    to_check.push(color);
+        let mut to_check = VecDeque::from(vec![start]);
+
+        while let Some(current) = to_check.pop_front() {
+            for next in edges[current].iter() {
+                if found.insert(*next) {
+                    to_check.push_back(*next);
                 }
             }
         }
         found
     }
 
-    pub fn count(&self, color: &Color) -> usize {
-        let contents = &self.rules.get(color).unwrap().contents;
-        contents
-            .values()
-            .map(|bs| bs.count * (1 + self.count(&bs.color)))
-            .sum()
+    /// Bags inside `starts` (including themselves), each listed after all bags it contains.
+    ///
+    /// Fails if there is a cycle instead of recursing forever.
+    fn post_order(&self, starts: &[usize]) -> Result<Vec<usize>> {
+        let mut state = vec![Visit::New; self.colors.len()];
+        let mut order = Vec::new();
+        // (bag, index of next content to visit)
+        let mut stack = Vec::new();
+
+        for start in starts.iter() {
+            if state[*start] != Visit::New {
+                continue;
+            }
+            state[*start] = Visit::Active;
+            stack.push((*start, 0));
+            self.visit(&mut stack, &mut state, &mut order)?;
+        }
+        Ok(order)
     }
 
-    /// Return a set of bag colors the given color is in (non-recursively).
-    fn is_in(&self, color: &Color) -> HashSet<Color> {
-        self.rules
-            .values()
-            .filter(|r| r.contains(color))
-            .map(|r| r.container.clone())
-            .collect()
+    fn visit(
+        &self,
+        stack: &mut Vec<(usize, usize)>,
+        state: &mut [Visit],
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        while let Some(&(node, child)) = stack.last() {
+            match self.contents[node].get(child) {
+                Some(&(next, _)) => {
+                    if let Some(top) = stack.last_mut() {
+                        top.1 += 1;
+                    }
+                    match state[next] {
+                        Visit::New => {
+                            state[next] = Visit::Active;
+                            stack.push((next, 0));
+                        }
+                        Visit::Active => {
+                            let pos = stack.iter().position(|(n, _)| *n == next).unwrap();
+                            let cycle: Vec<_> = stack[pos..]
+                                .iter()
+                                .map(|(n, _)| n)
+                                .chain(std::iter::once(&next))
+                                .map(|n| self.colors[*n].to_string())
+                                .collect();
+                            bail!("Cycle detected: {}", cycle.join(" -> "));
+                        }
+                        Visit::Done => {}
+                    }
+                }
+                None => {
+                    state[node] = Visit::Done;
+                    order.push(node);
+                    stack.pop();
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub fn print(&self) {
-        for r in self.rules.iter() {
-            println!("{:#?}", r);
+    /// Select all bags, or only those related to the given color (containing it or inside it).
+    fn select(&self, around: Option<&Color>) -> Result<Vec<bool>> {
+        match around {
+            None => Ok(vec![true; self.colors.len()]),
+            Some(color) => {
+                let center = self.id(color)?;
+                let mut selected = vec![false; self.colors.len()];
+                selected[center] = true;
+                for idx in self
+                    .ancestors(center)
+                    .into_iter()
+                    .chain(self.descendants(center))
+                {
+                    selected[idx] = true;
+                }
+                Ok(selected)
+            }
         }
     }
+
+    fn edges<'a>(
+        &'a self,
+        selected: &'a [bool],
+    ) -> impl Iterator<Item = (usize, usize, usize)> + 'a {
+        self.contents
+            .iter()
+            .enumerate()
+            .filter(move |(outer, _)| selected[*outer])
+            .flat_map(|(outer, contents)| {
+                contents
+                    .iter()
+                    .map(move |(inner, count)| (outer, *inner, *count))
+            })
+            .filter(move |(_, inner, _)| selected[*inner])
+    }
+
+    pub fn to_dot(&self, around: Option<&Color>) -> Result<String> {
+        let selected = self.select(around)?;
+        let mut rv = String::from("digraph bags {\n");
+        for (idx, color) in self.colors.iter().enumerate() {
+            if !selected[idx] {
+                continue;
+            }
+            if Some(color) == around {
+                rv.push_str(&format!("  \"{}\" [style=filled];\n", color));
+            } else {
+                rv.push_str(&format!("  \"{}\";\n", color));
+            }
+        }
+        for (outer, inner, count) in self.edges(&selected) {
+            rv.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label={}];\n",
+                self.colors[outer], self.colors[inner], count
+            ));
+        }
+        rv.push_str("}\n");
+        Ok(rv)
+    }
+
+    pub fn to_graphml(&self, around: Option<&Color>) -> Result<String> {
+        let selected = self.select(around)?;
+        let mut rv = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">
+  <key id=\"color\" for=\"node\" attr.name=\"color\" attr.type=\"string\"/>
+  <key id=\"count\" for=\"edge\" attr.name=\"count\" attr.type=\"int\"/>
+  <graph id=\"bags\" edgedefault=\"directed\">
+",
+        );
+        for (idx, color) in self.colors.iter().enumerate() {
+            if selected[idx] {
+                rv.push_str(&format!(
+                    "    <node id=\"n{}\"><data key=\"color\">{}</data></node>\n",
+                    idx, color
+                ));
+            }
+        }
+        for (outer, inner, count) in self.edges(&selected) {
+            rv.push_str(&format!(
+                "    <edge source=\"n{}\" target=\"n{}\"><data key=\"count\">{}</data></edge>\n",
+                outer, inner, count
+            ));
+        }
+        rv.push_str("  </graph>\n</graphml>\n");
+        Ok(rv)
+    }
 }
 
-fn part1(rules: &RuleSet) {
+fn part1(rules: &RuleSet) -> Result<()> {
     let golden = Color::from("shiny gold");
-    let containers = rules.contain(&golden);
+    let containers = rules.contain(&golden)?;
     println!("{} is in {} bags.", golden, containers.len());
+    Ok(())
 }
 
-fn part2(rules: &RuleSet) {
+fn part2(rules: &RuleSet) -> Result<()> {
     let golden = Color::from("shiny gold");
-    let count = rules.count(&golden);
+    let count = rules.count(&golden)?;
     println!("{} contains {} bags.", golden, count);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
+    #[test]
+    fn test_example() -> Result<()> {
+        let rules = RuleSet::parse(EXAMPLE)?;
+        let golden = Color::from("shiny gold");
+        assert_eq!(rules.contain(&golden)?.len(), 4);
+        assert_eq!(rules.count(&golden)?, 32);
+        assert!(rules.count(&Color::from("no such")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deep_shared() -> Result<()> {
+        // every level contains two bags of the next one -> 2^60 bags without memoisation
+        let level = |i: u8| format!("l{}{}", (b'a' + i / 26) as char, (b'a' + i % 26) as char);
+        let rules: Vec<String> = (0..60)
+            .flat_map(|i| {
+                let (outer, inner) = (level(i), level(i + 1));
+                vec![
+                    format!(
+                        "{} x bags contain 1 {} x bag, 1 {} y bag.",
+                        outer, inner, inner
+                    ),
+                    format!(
+                        "{} y bags contain 1 {} x bag, 1 {} y bag.",
+                        outer, inner, inner
+                    ),
+                ]
+            })
+            .collect();
+        let rules = RuleSet::parse(&rules.join("\n"))?;
+        assert_eq!(rules.count(&Color::from("laa x"))?, (1 << 61) - 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycle() -> Result<()> {
+        let rules = RuleSet::parse(
+            "light red bags contain 1 dark blue bag.
+dark blue bags contain 2 light red bags.",
+        )?;
+        let err = rules.count(&Color::from("light red")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cycle detected: light red -> dark blue -> light red"
+        );
+        assert_eq!(rules.contain(&Color::from("light red"))?.len(), 2);
+        assert!(rules.depth(None).is_err());
+        assert!(rules
+            .paths(&Color::from("light red"), &Color::from("dark blue"))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_queries() -> Result<()> {
+        let rules = RuleSet::parse(EXAMPLE)?;
+        let query = |args: &[&str]| -> Result<String> {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            Query::from_args(&args)?.run(&rules)
+        };
+
+        assert_eq!(
+            query(&["containers", "shiny gold"])?,
+            "bright white\ndark orange\nlight red\nmuted yellow\n"
+        );
+        assert_eq!(query(&["count", "shiny", "gold"])?, "32\n");
+        assert_eq!(
+            query(&["paths", "light red", "shiny gold"])?,
+            "light red -> bright white -> shiny gold (x1)
+light red -> muted yellow -> shiny gold (x4)
+2 path(s), 5 bag(s) in total
+"
+        );
+        assert_eq!(query(&["roots"])?, "dark orange\nlight red\n");
+        assert_eq!(query(&["depth", "shiny gold"])?, "2\n");
+        assert_eq!(query(&["depth"])?, "4\n");
+        assert!(query(&["paths", "shiny gold"]).is_err());
+        assert!(query(&["count", "shiny"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let rules = RuleSet::parse(EXAMPLE)?;
+        let dot = rules.to_dot(Some(&Color::from("dark olive")))?;
+        assert_eq!(
+            dot,
+            "digraph bags {
+  \"light red\";
+  \"bright white\";
+  \"muted yellow\";
+  \"dark orange\";
+  \"shiny gold\";
+  \"faded blue\";
+  \"dark olive\" [style=filled];
+  \"dotted black\";
+  \"light red\" -> \"bright white\" [label=1];
+  \"light red\" -> \"muted yellow\" [label=2];
+  \"bright white\" -> \"shiny gold\" [label=1];
+  \"muted yellow\" -> \"shiny gold\" [label=2];
+  \"muted yellow\" -> \"faded blue\" [label=9];
+  \"dark orange\" -> \"bright white\" [label=3];
+  \"dark orange\" -> \"muted yellow\" [label=4];
+  \"shiny gold\" -> \"dark olive\" [label=1];
+  \"dark olive\" -> \"faded blue\" [label=3];
+  \"dark olive\" -> \"dotted black\" [label=4];
+}
+"
+        );
+        let graphml = rules.to_graphml(None)?;
+        assert_eq!(graphml.matches("<node ").count(), 9);
+        assert_eq!(graphml.matches("<edge ").count(), 13);
+        Ok(())
+    }
 }
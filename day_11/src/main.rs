@@ -1,47 +1,316 @@
 use anyhow::{bail, Context, Result};
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::env;
 use std::fmt;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs::{create_dir_all, read_to_string, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
+    let options = Options::from_args(&args[1..])?;
 
     let grid = Grid::read_from(&input)?;
-    println!("{}", grid);
 
-    part1(grid.clone())?;
-    part2(grid.clone())?;
+    if args.len() == 1 {
+        println!("{}", grid);
+        part1(&grid)?;
+        part2(&grid)?;
+    } else {
+        let rules = options.rules.clone().unwrap_or_else(Rules::part1);
+        let mut automaton = Automaton::new(&grid, &rules);
+        let outcome = automaton.run(options.max_steps, |step, automaton| {
+            if let Some(dir) = &options.frames {
+                let path = dir.join(format!("frame_{:05}.ppm", step));
+                options.palette.write_ppm(
+                    &automaton.to_grid(),
+                    options.cell_size,
+                    File::create(path)?,
+                )?;
+            }
+            Ok(())
+        })?;
+        println!("{}", outcome);
+        println!("Number of occupied seats: {}", automaton.count_occupied());
+    }
 
     Ok(())
 }
 
-fn part1(grid: Grid) -> Result<()> {
-    let fixed = grid.update_till_fixed();
-    let num_occupied = fixed.count(Position::Occupied);
+/// Occupied seats once the seating settles, fails if it oscillates instead.
+fn settle(grid: &Grid, rules: &Rules) -> Result<usize> {
+    let mut automaton = Automaton::new(grid, rules);
+    match automaton.run(None, |_, _| Ok(()))? {
+        Outcome::Cycle { period: 1, .. } => Ok(automaton.count_occupied()),
+        outcome => bail!("Seating does not settle: {}", outcome),
+    }
+}
+
+fn part1(grid: &Grid) -> Result<()> {
+    let num_occupied = settle(grid, &Rules::part1())?;
 
     println!("(part1) Number of occupied seats: {}", num_occupied);
 
     Ok(())
 }
 
-fn part2(grid: Grid) -> Result<()> {
-    let fixed = grid.update_directional_till_fixed();
-    let num_occupied = fixed.count(Position::Occupied);
+fn part2(grid: &Grid) -> Result<()> {
+    let num_occupied = settle(grid, &Rules::part2())?;
 
     println!("(part2) Number of occupied seats: {}", num_occupied);
 
     Ok(())
 }
 
-#[derive(Clone, PartialEq)]
+struct Options {
+    rules: Option<Rules>,
+    max_steps: Option<usize>,
+    frames: Option<PathBuf>,
+    cell_size: usize,
+    palette: Palette,
+}
+
+impl Options {
+    /// Usage: `day_11 <input> [--rules <spec>] [--max-steps <n>] [--frames <dir>]
+    /// [--cell-size <px>] [--palette <floor>,<empty>,<occupied>]`
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut options = Self {
+            rules: None,
+            max_steps: None,
+            frames: None,
+            cell_size: 4,
+            palette: Palette::default(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .with_context(|| format!("{} requires a value", arg))?;
+            let number = || -> Result<usize> {
+                value
+                    .parse()
+                    .with_context(|| format!("Invalid number for {}: {}", arg, value))
+            };
+            match arg.as_str() {
+                "--rules" => options.rules = Some(Rules::parse(value)?),
+                "--max-steps" => options.max_steps = Some(number()?),
+                "--frames" => {
+                    let dir = PathBuf::from(value);
+                    create_dir_all(&dir)?;
+                    options.frames = Some(dir);
+                }
+                "--cell-size" => {
+                    options.cell_size = number()?;
+                    if options.cell_size == 0 {
+                        bail!("Cell size must be positive.");
+                    }
+                }
+                "--palette" => options.palette = Palette::parse(value)?,
+                _ => bail!("Unknown argument: {}", arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Colors used to render floor, empty and occupied cells.
+#[derive(Clone, Debug, PartialEq)]
+struct Palette {
+    floor: [u8; 3],
+    empty: [u8; 3],
+    occupied: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            floor: [0x20, 0x20, 0x20],
+            empty: [0x40, 0xa0, 0x40],
+            occupied: [0xd0, 0x40, 0x40],
+        }
+    }
+}
+
+impl Palette {
+    /// Parse three comma-separated hex colors, e.g. `202020,40a040,d04040`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let colors = spec
+            .split(',')
+            .map(|c| {
+                let c = c.trim().trim_start_matches('#');
+                let value = u32::from_str_radix(c, 16)
+                    .ok()
+                    .filter(|_| c.len() == 6)
+                    .with_context(|| format!("Invalid color: {}", c))?;
+                Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match colors[..] {
+            [floor, empty, occupied] => Ok(Self {
+                floor,
+                empty,
+                occupied,
+            }),
+            _ => bail!("Palette needs exactly three colors, got: {}", spec),
+        }
+    }
+
+    fn color(&self, position: &Position) -> [u8; 3] {
+        match position {
+            Position::Floor => self.floor,
+            Position::Empty => self.empty,
+            Position::Occupied => self.occupied,
+        }
+    }
+
+    /// Render the grid as binary PPM with `cell_size` pixels per cell.
+    pub fn write_ppm(&self, grid: &Grid, cell_size: usize, w: impl Write) -> Result<()> {
+        let mut w = BufWriter::new(w);
+        write!(
+            w,
+            "P6\n{} {}\n255\n",
+            grid.width * cell_size,
+            grid.height * cell_size
+        )?;
+        let mut row = Vec::with_capacity(3 * grid.width * cell_size);
+        for line in grid.lines.iter() {
+            row.clear();
+            for pos in line.iter() {
+                for _ in 0..cell_size {
+                    row.extend_from_slice(&self.color(pos));
+                }
+            }
+            for _ in 0..cell_size {
+                w.write_all(&row)?;
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+/// How a simulation ended.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// The generation after `entry` steps repeats every `period` steps, a fixed point has
+    /// period 1.
+    Cycle { entry: usize, period: usize },
+    /// No repetition within the given number of steps.
+    Limit(usize),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Cycle { entry, period: 1 } => write!(f, "Fixed after {} steps", entry),
+            Outcome::Cycle { entry, period } => {
+                write!(
+                    f,
+                    "Cycle of period {} entered after {} steps",
+                    period, entry
+                )
+            }
+            Outcome::Limit(steps) => write!(f, "No repetition within {} steps", steps),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Neighbourhood {
+    /// The eight surrounding cells.
+    Adjacent,
+    /// The first non-transparent cell in each of the eight directions, optionally only up to
+    /// the given distance.
+    LineOfSight(Option<usize>),
+}
+
+/// Seating rules: which cells a seat considers and how many occupied ones it tolerates.
+#[derive(Clone, Debug, PartialEq)]
+struct Rules {
+    neighbourhood: Neighbourhood,
+    // an empty seat becomes occupied if at most this many neighbours are occupied
+    birth_max: usize,
+    // an occupied seat stays occupied if at most this many neighbours are occupied
+    survival_max: usize,
+    // cell types line of sight passes through
+    transparent: Vec<Position>,
+}
+
+impl Rules {
+    pub fn part1() -> Self {
+        Self {
+            neighbourhood: Neighbourhood::Adjacent,
+            birth_max: 0,
+            survival_max: 3,
+            transparent: vec![Position::Floor],
+        }
+    }
+
+    pub fn part2() -> Self {
+        Self {
+            neighbourhood: Neighbourhood::LineOfSight(None),
+            birth_max: 0,
+            survival_max: 4,
+            transparent: vec![Position::Floor],
+        }
+    }
+
+    /// Parse whitespace separated `key=value` pairs on top of the part 1 rules, e.g.
+    /// `neighbourhood=sight:10 birth=0 survival=4 transparent=.`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut rules = Self::part1();
+
+        for pair in spec.split_whitespace() {
+            let (key, value) = pair
+                .split_once('=')
+                .with_context(|| format!("Expected key=value, got: {}", pair))?;
+            let number = |v: &str| -> Result<usize> {
+                v.parse()
+                    .with_context(|| format!("Invalid number for {}: {}", key, v))
+            };
+
+            match key {
+                "neighbourhood" => {
+                    rules.neighbourhood = match value.split_once(':') {
+                        None if value == "adjacent" => Neighbourhood::Adjacent,
+                        None if value == "sight" => Neighbourhood::LineOfSight(None),
+                        Some(("sight", max)) => match number(max)? {
+                            0 => bail!("sight distance must be at least 1"),
+                            max => Neighbourhood::LineOfSight(Some(max)),
+                        },
+                        _ => bail!("Invalid neighbourhood: {}", value),
+                    };
+                }
+                "birth" => rules.birth_max = number(value)?,
+                "survival" => rules.survival_max = number(value)?,
+                "transparent" => {
+                    rules.transparent = value
+                        .chars()
+                        .map(Position::parse)
+                        .collect::<Result<Vec<_>>>()?;
+                }
+                _ => bail!("Unknown rule: {}", key),
+            }
+        }
+        Ok(rules)
+    }
+
+    fn max_distance(&self) -> Option<usize> {
+        match self.neighbourhood {
+            Neighbourhood::Adjacent => Some(1),
+            Neighbourhood::LineOfSight(max) => max,
+        }
+    }
+
+    fn is_transparent(&self, position: &Position) -> bool {
+        self.transparent.contains(position)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum Position {
     Floor,
     Empty,
@@ -60,10 +329,6 @@ impl Position {
             }
         }
     }
-
-    pub fn count_in(&self, slice: &[Position]) -> usize {
-        slice.iter().filter(move |e| self == *e).count()
-    }
 }
 
 impl fmt::Display for Position {
@@ -87,17 +352,21 @@ struct Grid {
 
 impl Grid {
     pub fn read_from(path: &Path) -> Result<Self> {
-        let mut raw_lines = io::BufReader::new(File::open(&path)?).lines();
+        Self::parse(&read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut raw_lines = content.lines();
 
         let mut lines = Vec::new();
-        let first_raw_line = raw_lines.next().with_context(|| "Input has no lines")??;
-        let first_line = Self::read_line(&first_raw_line)?;
+        let first_raw_line = raw_lines.next().with_context(|| "Input has no lines")?;
+        let first_line = Self::read_line(first_raw_line)?;
 
         let width = first_line.len();
         lines.push(first_line);
 
         for raw in raw_lines {
-            let parsed = Self::read_line(&raw?)?;
+            let parsed = Self::read_line(raw)?;
             if parsed.len() != width {
                 bail!("Lines do not have the same width..");
             }
@@ -112,202 +381,537 @@ impl Grid {
         })
     }
 
-    pub fn count(&self, position: Position) -> usize {
-        let mut count = 0;
-        for line in self.lines.iter() {
-            count += position.count_in(&line[..]);
+    fn read_line(line: &str) -> Result<Vec<Position>> {
+        let mut rv: Vec<_> = Vec::new();
+        for c in line.chars() {
+            rv.push(Position::parse(c)?);
         }
-        count
+        Ok(rv)
     }
 
-    pub fn update(&self) -> Self {
-        let mut lines = Vec::with_capacity(self.height);
+    fn reached_edge(&self, x: i64, y: i64) -> bool {
+        x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64
+    }
+}
 
-        for y in 0..(self.height as i64) {
-            let mut newline = Vec::with_capacity(self.width);
-            for x in 0..(self.width as i64) {
-                newline.push(self.update_at(x, y));
-            }
-            lines.push(newline);
-        }
+/// Fixed-size bit set.
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct BitSet {
+    words: Vec<u64>,
+}
 
-        Self { lines, ..*self }
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
     }
 
-    pub fn update_directional(&self) -> Self {
-        let mut lines = Vec::with_capacity(self.height);
+    pub fn get(&self, idx: usize) -> bool {
+        self.words[idx / 64] & (1 << (idx % 64)) > 0
+    }
 
-        for y in 0..(self.height as i64) {
-            let mut newline = Vec::with_capacity(self.width);
-            for x in 0..(self.width as i64) {
-                newline.push(self.update_directional_at(x, y));
-            }
-            lines.push(newline);
+    pub fn set(&mut self, idx: usize, value: bool) {
+        if value {
+            self.words[idx / 64] |= 1 << (idx % 64);
+        } else {
+            self.words[idx / 64] &= !(1 << (idx % 64));
         }
-
-        Self { lines, ..*self }
     }
 
-    pub fn update_till_fixed(self) -> Self {
-        let mut old = self;
-        let mut step = 0;
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
 
-        println!("Initial: {}", old);
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
 
-        loop {
-            step += 1;
-            let new = old.update();
-            println!("After step #{}: {}", step, new);
+/// Seat automaton for a fixed rule set.
+///
+/// Only seats are simulated (floor never changes). For every seat, the seats it can see are
+/// precomputed once, occupancy is stored bit-packed in two buffers that are swapped after each
+/// step, and only seats next to a change are re-evaluated.
+#[derive(Clone)]
+struct Automaton {
+    width: usize,
+    height: usize,
+    rules: Rules,
+    // transparency of empty and occupied seats, indexed by occupancy
+    seat_transparent: [bool; 2],
+    // cell index (y * width + x) of every seat
+    seats: Vec<usize>,
+    // seats visible from seat s in direction d are
+    // ray_seats[ray_offsets[8 * s + d]..ray_offsets[8 * s + d + 1]], nearest first
+    ray_offsets: Vec<u32>,
+    ray_seats: Vec<u32>,
+    // rays whose last seat is at the maximum distance, i.e. is seen regardless of transparency
+    ray_limited: BitSet,
+    // seats that have seat s on one of their rays:
+    // watchers[watcher_offsets[s]..watcher_offsets[s + 1]]
+    watcher_offsets: Vec<u32>,
+    watchers: Vec<u32>,
+    current: BitSet,
+    next: BitSet,
+    // seats to re-evaluate in the next step, unless all of them are
+    all_dirty: bool,
+    dirty: Vec<u32>,
+    is_dirty: BitSet,
+    changed: Vec<u32>,
+}
 
-            if old == new {
-                return new;
-            } else {
-                old = new;
+const DIRECTIONS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+impl Automaton {
+    pub fn new(grid: &Grid, rules: &Rules) -> Self {
+        let mut seat_of_cell = vec![u32::MAX; grid.width * grid.height];
+        let mut seats = Vec::new();
+        for (y, line) in grid.lines.iter().enumerate() {
+            for (x, pos) in line.iter().enumerate() {
+                if *pos != Position::Floor {
+                    seat_of_cell[y * grid.width + x] = seats.len() as u32;
+                    seats.push(y * grid.width + x);
+                }
             }
         }
-    }
 
-    pub fn update_directional_till_fixed(self) -> Self {
-        let mut old = self;
-        let mut step = 0;
-
-        println!("Initial: {}", old);
+        // if seats are opaque, a ray ends at the first seat
+        let seats_transparent =
+            rules.is_transparent(&Position::Empty) || rules.is_transparent(&Position::Occupied);
+        let max_distance = rules.max_distance();
+
+        let mut ray_offsets = Vec::with_capacity(8 * seats.len() + 1);
+        let mut ray_seats = Vec::new();
+        let mut ray_limited = BitSet::new(8 * seats.len());
+        ray_offsets.push(0);
+        for cell in seats.iter() {
+            let (x, y) = ((cell % grid.width) as i64, (cell / grid.width) as i64);
+            for (dx, dy) in DIRECTIONS.iter() {
+                let mut distance = 1;
+                while !grid.reached_edge(x + dx * distance, y + dy * distance) {
+                    let (nx, ny) = (x + dx * distance, y + dy * distance);
+                    let neighbour = seat_of_cell[ny as usize * grid.width + nx as usize];
+                    if neighbour != u32::MAX {
+                        ray_seats.push(neighbour);
+                    }
+                    let transparent = if neighbour == u32::MAX {
+                        rules.is_transparent(&Position::Floor)
+                    } else {
+                        seats_transparent
+                    };
+                    if Some(distance as usize) == max_distance {
+                        ray_limited.set(ray_offsets.len() - 1, neighbour != u32::MAX);
+                        break;
+                    }
+                    if !transparent {
+                        break;
+                    }
+                    distance += 1;
+                }
+                ray_offsets.push(ray_seats.len() as u32);
+            }
+        }
 
-        loop {
-            step += 1;
-            let new = old.update_directional();
-            println!("After step #{}: {}", step, new);
+        let mut watcher_offsets = vec![0u32; seats.len() + 1];
+        for seen in ray_seats.iter() {
+            watcher_offsets[*seen as usize + 1] += 1;
+        }
+        for idx in 0..seats.len() {
+            watcher_offsets[idx + 1] += watcher_offsets[idx];
+        }
+        let mut fill = watcher_offsets.clone();
+        let mut watchers = vec![0u32; ray_seats.len()];
+        for seat in 0..seats.len() {
+            for ray in ray_offsets[8 * seat]..ray_offsets[8 * seat + 8] {
+                let seen = ray_seats[ray as usize] as usize;
+                watchers[fill[seen] as usize] = seat as u32;
+                fill[seen] += 1;
+            }
+        }
 
-            if old == new {
-                return new;
-            } else {
-                old = new;
+        let mut current = BitSet::new(seats.len());
+        for (idx, cell) in seats.iter().enumerate() {
+            if grid.lines[cell / grid.width][cell % grid.width] == Position::Occupied {
+                current.set(idx, true);
             }
         }
-    }
 
-    fn read_line(line: &str) -> Result<Vec<Position>> {
-        let mut rv: Vec<_> = Vec::new();
-        for c in line.chars() {
-            rv.push(Position::parse(c)?);
+        Self {
+            width: grid.width,
+            height: grid.height,
+            rules: rules.clone(),
+            seat_transparent: [
+                rules.is_transparent(&Position::Empty),
+                rules.is_transparent(&Position::Occupied),
+            ],
+            all_dirty: true,
+            dirty: Vec::new(),
+            is_dirty: BitSet::new(seats.len()),
+            next: current.clone(),
+            current,
+            changed: Vec::new(),
+            seats,
+            ray_offsets,
+            ray_seats,
+            ray_limited,
+            watcher_offsets,
+            watchers,
         }
-        Ok(rv)
     }
 
-    fn reached_edge(&self, x: i64, y: i64) -> bool {
-        x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64
+    pub fn count_occupied(&self) -> usize {
+        self.current.count_ones()
     }
 
-    fn get_neighbors(&self, x: i64, y: i64) -> Vec<Position> {
-        let mut rv = Vec::new();
-        for dy in (-1)..2 {
-            for dx in (-1)..2 {
-                if dx == 0 && dy == 0 {
-                    continue;
+    /// Number of occupied seats visible from the given seat.
+    fn visible_occupied(&self, seat: usize) -> usize {
+        let mut count = 0;
+        for dir in 0..8 {
+            let ray = &self.ray_seats[self.ray_offsets[8 * seat + dir] as usize
+                ..self.ray_offsets[8 * seat + dir + 1] as usize];
+            let limited = self.ray_limited.get(8 * seat + dir);
+            for (idx, seen) in ray.iter().enumerate() {
+                let occupied = self.current.get(*seen as usize);
+                if !self.seat_transparent[occupied as usize] || (limited && idx + 1 == ray.len()) {
+                    count += occupied as usize;
+                    break;
                 }
-                rv.push(self.get_pos(x + dx, y + dy));
             }
         }
-        rv
+        count
     }
 
-    fn get_neighbors_directional(&self, x: i64, y: i64) -> Vec<Position> {
-        let mut rv = Vec::new();
-        for dy in (-1)..2 {
-            for dx in (-1)..2 {
-                if dx == 0 && dy == 0 {
-                    continue;
-                }
-                let mut multiplier = 1;
-                while !self.reached_edge(x + dx * multiplier, y + dy * multiplier) {
-                    let neighbour_x = x + multiplier * dx;
-                    let neighbour_y = y + multiplier * dy;
-                    match self.get_pos(neighbour_x, neighbour_y) {
-                        Position::Floor if self.reached_edge(neighbour_x, neighbour_y) => {
-                            rv.push(Position::Floor)
-                        }
-                        Position::Floor => {
-                            multiplier += 1;
-                            continue;
-                        }
-                        other => {
-                            rv.push(other);
-                            break;
-                        }
-                    }
-                }
+    /// Advance one generation, returning the number of seats that changed.
+    pub fn step(&mut self) -> usize {
+        self.changed.clear();
+        let num_dirty = if self.all_dirty {
+            self.seats.len()
+        } else {
+            self.dirty.len()
+        };
+        for idx in 0..num_dirty {
+            let seat = if self.all_dirty {
+                idx
+            } else {
+                self.dirty[idx] as usize
+            };
+            let occupied = self.current.get(seat);
+            let count = self.visible_occupied(seat);
+            let new = if occupied {
+                count <= self.rules.survival_max
+            } else {
+                count <= self.rules.birth_max
+            };
+            if new != occupied {
+                self.next.set(seat, new);
+                self.changed.push(seat as u32);
             }
         }
-        rv
-    }
+        std::mem::swap(&mut self.current, &mut self.next);
 
-    fn get_pos(&self, x: i64, y: i64) -> Position {
-        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
-            Position::Floor
+        // bring the old buffer up to date and collect seats affected by the changes
+        for seat in self.dirty.drain(..) {
+            self.is_dirty.set(seat as usize, false);
+        }
+        // if most seats are affected anyway, scanning all of them in order is cheaper
+        self.all_dirty = self.changed.len() > self.seats.len() / 8;
+        if self.all_dirty {
+            self.next.clone_from(&self.current);
         } else {
-            self.lines[y as usize][x as usize].clone()
+            for seat in self.changed.iter() {
+                let seat = *seat as usize;
+                self.next.set(seat, self.current.get(seat));
+
+                let watchers = &self.watchers
+                    [self.watcher_offsets[seat] as usize..self.watcher_offsets[seat + 1] as usize];
+                for affected in std::iter::once(seat as u32).chain(watchers.iter().cloned()) {
+                    if !self.is_dirty.get(affected as usize) {
+                        self.is_dirty.set(affected as usize, true);
+                        self.dirty.push(affected);
+                    }
+                }
+            }
         }
+        self.changed.len()
     }
 
-    fn update_at(&self, x: i64, y: i64) -> Position {
-        use Position::*;
+    /// Step until a generation repeats or `max_steps` are done.
+    ///
+    /// `observe` is called for the initial and every new generation. Generations are hashed to
+    /// find repetitions, which are then confirmed by simulating the cycle once more.
+    pub fn run(
+        &mut self,
+        max_steps: Option<usize>,
+        mut observe: impl FnMut(usize, &Automaton) -> Result<()>,
+    ) -> Result<Outcome> {
+        let mut seen: HashMap<u64, usize> = HashMap::new();
+        let mut step = 0;
 
-        let get_num_occupied = || -> usize { Occupied.count_in(&self.get_neighbors(x, y)[..]) };
+        observe(step, self)?;
+        seen.insert(self.current.fingerprint(), step);
 
-        match self.get_pos(x, y) {
-            Floor => Floor,
-            Empty => {
-                if get_num_occupied() == 0 {
-                    Occupied
-                } else {
-                    Empty
-                }
+        loop {
+            if Some(step) == max_steps {
+                return Ok(Outcome::Limit(step));
             }
-            Occupied => {
-                if get_num_occupied() >= 4 {
-                    Empty
-                } else {
-                    Occupied
+            let changed = self.step();
+            step += 1;
+            if changed == 0 {
+                return Ok(Outcome::Cycle {
+                    entry: step - 1,
+                    period: 1,
+                });
+            }
+            observe(step, self)?;
+
+            let fingerprint = self.current.fingerprint();
+            if let Some(prev) = seen.get(&fingerprint) {
+                if let Some(period) = self.confirm_period(step - prev) {
+                    return Ok(Outcome::Cycle {
+                        entry: *prev,
+                        period,
+                    });
                 }
             }
+            seen.insert(fingerprint, step);
         }
     }
 
-    fn update_directional_at(&self, x: i64, y: i64) -> Position {
-        use Position::*;
-
-        let get_num_occupied =
-            || -> usize { Occupied.count_in(&self.get_neighbors_directional(x, y)[..]) };
+    /// Check on a copy whether the current generation returns within `max_period` steps.
+    fn confirm_period(&self, max_period: usize) -> Option<usize> {
+        let mut probe = self.clone();
+        (1..=max_period).find(|_| {
+            probe.step();
+            probe.current == self.current
+        })
+    }
 
-        match self.get_pos(x, y) {
-            Floor => Floor,
-            Empty => {
-                if get_num_occupied() == 0 {
-                    Occupied
-                } else {
-                    Empty
-                }
-            }
-            Occupied => {
-                if get_num_occupied() >= 5 {
-                    Empty
-                } else {
-                    Occupied
-                }
-            }
+    pub fn to_grid(&self) -> Grid {
+        let mut lines = vec![vec![Position::Floor; self.width]; self.height];
+        for (idx, cell) in self.seats.iter().enumerate() {
+            lines[cell / self.width][cell % self.width] = if self.current.get(idx) {
+                Position::Occupied
+            } else {
+                Position::Empty
+            };
+        }
+        Grid {
+            lines,
+            width: self.width,
+            height: self.height,
         }
     }
 }
 
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Grid ({}x{})\n", self.width, self.height)?;
+        writeln!(f, "Grid ({}x{})", self.width, self.height)?;
         for line in self.lines.iter() {
             for pos in line.iter() {
                 write!(f, "{}", pos)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "L.LL.LL.LL
+LLLLLLL.LL
+L.L.L..L..
+LLLL.LL.LL
+L.LL.LL.LL
+L.LLLLL.LL
+..L.L.....
+LLLLLLLLLL
+L.LLLLLL.L
+L.LLLLL.LL";
+
+    #[test]
+    fn test_parts() -> Result<()> {
+        let grid = Grid::parse(EXAMPLE)?;
+        assert_eq!(settle(&grid, &Rules::part1())?, 37);
+        assert_eq!(settle(&grid, &Rules::part2())?, 26);
+
+        let mut automaton = Automaton::new(&grid, &Rules::part1());
+        let mut observed = Vec::new();
+        let outcome = automaton.run(None, |step, a| {
+            observed.push((step, a.count_occupied()));
+            Ok(())
+        })?;
+        assert_eq!(
+            outcome,
+            Outcome::Cycle {
+                entry: 5,
+                period: 1
+            }
+        );
+        assert_eq!(
+            observed,
+            vec![(0, 0), (1, 71), (2, 20), (3, 51), (4, 30), (5, 37)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycles() -> Result<()> {
+        // neighbouring seats taken at once have to be left at once
+        let grid = Grid::parse("LL")?;
+        let rules = Rules::parse("survival=0")?;
+        assert_eq!(
+            Automaton::new(&grid, &rules).run(None, |_, _| Ok(()))?,
+            Outcome::Cycle {
+                entry: 0,
+                period: 2
+            }
+        );
+        assert!(settle(&grid, &rules).is_err());
+        assert_eq!(
+            Automaton::new(&grid, &rules).run(Some(1), |_, _| Ok(()))?,
+            Outcome::Limit(1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ppm() -> Result<()> {
+        let grid = Grid::parse("L#\n..")?;
+        let palette = Palette::parse("000000,00ff00,#ff0000")?;
+        let mut ppm = Vec::new();
+        palette.write_ppm(&grid, 2, &mut ppm)?;
+        let header = b"P6\n4 4\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels.len(), 4 * 4 * 3);
+        assert_eq!(&pixels[..6], &[0, 255, 0, 0, 255, 0]);
+        assert_eq!(&pixels[6..12], &[255, 0, 0, 255, 0, 0]);
+        assert!(pixels[24..].iter().all(|p| *p == 0));
+        assert!(Palette::parse("000000,00ff00").is_err());
+        assert!(Palette::parse("000000,00ff00,red").is_err());
+        Ok(())
+    }
+
+    /// Straightforward simulation to check the automaton against.
+    fn naive_step(grid: &Grid, rules: &Rules) -> Grid {
+        let mut next = grid.clone();
+        for y in 0..grid.height as i64 {
+            for x in 0..grid.width as i64 {
+                let mut count = 0;
+                for (dx, dy) in DIRECTIONS.iter() {
+                    let mut distance = 1;
+                    while !grid.reached_edge(x + dx * distance, y + dy * distance) {
+                        let pos =
+                            &grid.lines[(y + dy * distance) as usize][(x + dx * distance) as usize];
+                        if Some(distance as usize) == rules.max_distance()
+                            || !rules.is_transparent(pos)
+                        {
+                            count += (*pos == Position::Occupied) as usize;
+                            break;
+                        }
+                        distance += 1;
+                    }
+                }
+                let cell = &mut next.lines[y as usize][x as usize];
+                *cell = match cell.clone() {
+                    Position::Floor => Position::Floor,
+                    Position::Empty if count <= rules.birth_max => Position::Occupied,
+                    Position::Occupied if count > rules.survival_max => Position::Empty,
+                    other => other,
+                };
+            }
+        }
+        next
+    }
+
+    #[test]
+    fn test_against_naive() -> Result<()> {
+        // deterministic pseudo-random layout
+        let mut state: u64 = 42;
+        let content: Vec<String> = (0..40)
+            .map(|_| {
+                (0..60)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        ['.', 'L', 'L', '#'][(state >> 62) as usize]
+                    })
+                    .collect()
+            })
+            .collect();
+        let grid = Grid::parse(&content.join("\n"))?;
+
+        for spec in [
+            "",
+            "neighbourhood=sight survival=4",
+            "neighbourhood=sight:3 birth=1 survival=2",
+            "neighbourhood=sight transparent=.L",
+            "neighbourhood=sight:4 transparent=.#",
+            "neighbourhood=adjacent birth=2 transparent=",
+        ]
+        .iter()
+        {
+            let rules = Rules::parse(spec)?;
+            let mut automaton = Automaton::new(&grid, &rules);
+            let mut expected = grid.clone();
+            for _ in 0..20 {
+                expected = naive_step(&expected, &rules);
+                automaton.step();
+                assert!(automaton.to_grid() == expected, "mismatch for {}", spec);
+                assert_eq!(
+                    automaton.count_occupied(),
+                    expected
+                        .lines
+                        .iter()
+                        .flatten()
+                        .filter(|p| **p == Position::Occupied)
+                        .count()
+                );
+            }
         }
         Ok(())
     }
+
+    #[test]
+    fn test_rules_parse() -> Result<()> {
+        assert_eq!(Rules::parse("")?, Rules::part1());
+        assert_eq!(
+            Rules::parse("neighbourhood=sight survival=4")?,
+            Rules::part2()
+        );
+        let rules = Rules::parse("neighbourhood=sight:2 birth=1 transparent=.L")?;
+        assert_eq!(rules.neighbourhood, Neighbourhood::LineOfSight(Some(2)));
+        assert_eq!(rules.birth_max, 1);
+        assert_eq!(rules.transparent, vec![Position::Floor, Position::Empty]);
+        assert!(Rules::parse("neighbourhood=moore").is_err());
+        assert!(Rules::parse("birth").is_err());
+        assert!(Rules::parse("neighbourhood=sight:0").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_limited_sight() -> Result<()> {
+        let grid = Grid::parse("#...L.#")?;
+        let sees = |max: usize| -> Result<usize> {
+            let rules = Rules::parse(&format!("neighbourhood=sight:{} transparent=.L", max))?;
+            Ok(Automaton::new(&grid, &rules).visible_occupied(0))
+        };
+        assert_eq!(sees(5)?, 0);
+        assert_eq!(sees(6)?, 1);
+        Ok(())
+    }
 }
@@ -1,43 +1,101 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use nom::{
     branch::alt,
     character::complete::{char, digit1},
     Finish, IResult,
 };
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::env;
-use std::fs::File;
+use std::fmt;
+use std::fs::{write, File};
 use std::io::{self, BufRead};
+use std::ops::{Add, Mul, Neg};
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
     let instructions = Instruction::read_from(&input)?;
 
-    part1(&instructions[..]);
-    part2(&instructions[..]);
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            part1(&instructions[..])?;
+            part2(&instructions[..])?;
+        }
+        Some("svg") => {
+            let usage = "Usage: svg <v1|v2> <output> [--any-angle]";
+            let output = args.get(3).with_context(|| usage)?;
+            let any_angle = match args.get(4).map(|s| s.as_str()) {
+                None => false,
+                Some("--any-angle") => true,
+                Some(_) => bail!(usage),
+            };
+            let svg = match (args.get(2).map(|s| s.as_str()), any_angle) {
+                (Some("v1"), false) => svg(Ship::<i64>::new(), &instructions)?,
+                (Some("v1"), true) => svg(Ship::<f64>::new(), &instructions)?,
+                (Some("v2"), false) => svg(ShipV2::<i64>::new(), &instructions)?,
+                (Some("v2"), true) => svg(ShipV2::<f64>::new(), &instructions)?,
+                _ => bail!(usage),
+            };
+            write(output, svg)?;
+        }
+        Some("plan") => {
+            let usage = "Usage: plan <v1|v2> <x> <y> [--units]";
+            let target: Point = (
+                args.get(3).with_context(|| usage)?.parse()?,
+                args.get(4).with_context(|| usage)?.parse()?,
+            );
+            let cost = match args.get(5).map(|s| s.as_str()) {
+                None => Cost::Instructions,
+                Some("--units") => Cost::Units,
+                Some(_) => bail!(usage),
+            };
+            let mut ship: Box<dyn Navigator<i64>> = match args.get(2).map(|s| s.as_str()) {
+                Some("v1") => Box::new(Ship::new()),
+                Some("v2") => Box::new(ShipV2::new()),
+                _ => bail!(usage),
+            };
+            // plan from wherever the given instructions left the ship
+            ship.run_all(&instructions)?;
+            let plan = ship.plan(target, cost)?;
+            ship.run_all(&plan)?;
+            ensure!(
+                ship.position() == target,
+                "Plan does not reach {:?} but {:?}.",
+                target,
+                ship.position()
+            );
+            for inst in plan.iter() {
+                println!("{}", inst);
+            }
+        }
+        Some(cmd) => bail!("Unknown command: {}", cmd),
+    }
 
     Ok(())
 }
 
-fn part1(insts: &[Instruction]) {
-    let mut ship = Ship::new();
-    ship.run_all(insts);
+fn svg<T: Coordinate, N: Navigator<T>>(mut ship: N, insts: &[Instruction]) -> Result<String> {
+    ship.run_all(insts)?;
+    Ok(ship.trajectory().to_svg())
+}
+
+fn part1(insts: &[Instruction]) -> Result<()> {
+    let mut ship = Ship::<i64>::new();
+    ship.run_all(insts)?;
     let dist = ship.manhattan();
     println!("(part1) manhattan distance: {}", dist);
+    Ok(())
 }
 
-fn part2(insts: &[Instruction]) {
-    let mut ship = ShipV2::new();
-    ship.run_all(insts);
+fn part2(insts: &[Instruction]) -> Result<()> {
+    let mut ship = ShipV2::<i64>::new();
+    ship.run_all(insts)?;
     let dist = ship.manhattan();
     println!("(part2) manhattan distance: {}", dist);
+    Ok(())
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -80,11 +138,559 @@ impl Action {
     }
 }
 
-enum Direction {
-    North,
-    South,
-    East,
-    West,
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = match self {
+            Action::North => 'N',
+            Action::South => 'S',
+            Action::East => 'E',
+            Action::West => 'W',
+            Action::Forward => 'F',
+            Action::Left => 'L',
+            Action::Right => 'R',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+/// East/north coordinates.
+type Vector<T> = (T, T);
+
+/// Scalar of a navigator's coordinates, which also determines the turns it accepts.
+///
+/// `i64` is exact and only turns by multiples of 90 degrees, as in the puzzle. `f64` turns by
+/// any integer angle, rotating multiples of 90 degrees exactly.
+trait Coordinate:
+    Copy
+    + PartialEq
+    + fmt::Debug
+    + fmt::Display
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + Neg<Output = Self>
+{
+    fn from_i64(value: i64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    /// The integer value, if there is an exact one.
+    fn to_i64(self) -> Option<i64>;
+
+    fn abs(self) -> Self;
+
+    /// Rotate `v` as instructed by the turn instruction `inst`.
+    fn rotate(v: Vector<Self>, inst: &Instruction) -> Result<Vector<Self>>;
+}
+
+/// Rotate `v` counter-clockwise by `quarters` quarter turns.
+fn rotate_quarters<T: Coordinate>((x, y): Vector<T>, quarters: i64) -> Vector<T> {
+    match quarters.rem_euclid(4) {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    }
+}
+
+impl Coordinate for i64 {
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn to_i64(self) -> Option<i64> {
+        Some(self)
+    }
+
+    fn abs(self) -> Self {
+        i64::abs(self)
+    }
+
+    fn rotate(v: Vector<Self>, inst: &Instruction) -> Result<Vector<Self>> {
+        let degrees = turn_angle(inst).with_context(|| format!("{}: not a turn", inst))?;
+        ensure!(
+            degrees % 90 == 0,
+            "{}: only multiples of 90 are supported",
+            inst
+        );
+        Ok(rotate_quarters(v, degrees / 90))
+    }
+}
+
+impl Coordinate for f64 {
+    fn from_i64(value: i64) -> Self {
+        value as f64
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_i64(self) -> Option<i64> {
+        if self.fract() == 0.0 {
+            Some(self as i64)
+        } else {
+            None
+        }
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn rotate((x, y): Vector<Self>, inst: &Instruction) -> Result<Vector<Self>> {
+        let degrees = turn_angle(inst).with_context(|| format!("{}: not a turn", inst))?;
+        if degrees % 90 == 0 {
+            return Ok(rotate_quarters((x, y), degrees / 90));
+        }
+        let (sin, cos) = (degrees.rem_euclid(360) as f64).to_radians().sin_cos();
+        Ok((x * cos - y * sin, x * sin + y * cos))
+    }
+}
+
+/// Common interface of both ways to interpret the instructions.
+trait Navigator<T: Coordinate> {
+    fn run(&mut self, inst: &Instruction) -> Result<()>;
+
+    /// Current position of the ship.
+    fn position(&self) -> Vector<T>;
+
+    fn trajectory(&self) -> &Trajectory<T>;
+
+    /// Shortest instructions (with regard to `cost`) taking the ship to `target`.
+    fn plan(&self, target: Point, cost: Cost) -> Result<Vec<Instruction>>;
+
+    fn run_all(&mut self, insts: &[Instruction]) -> Result<()> {
+        for inst in insts.iter() {
+            self.run(inst)?;
+        }
+        Ok(())
+    }
+
+    fn manhattan(&self) -> T {
+        let (x, y) = self.position();
+        x.abs() + y.abs()
+    }
+}
+
+/// Integer east/north coordinates, used for planning.
+type Point = (i64, i64);
+
+/// What a plan minimizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cost {
+    /// Number of instructions.
+    Instructions,
+    /// Sum of all units, i.e. distances, forward moves and degrees turned.
+    Units,
+}
+
+fn integral<T: Coordinate>((x, y): Vector<T>) -> Result<Point> {
+    match (x.to_i64(), y.to_i64()) {
+        (Some(x), Some(y)) => Ok((x, y)),
+        _ => bail!("Cannot plan from non-integral state ({}, {}).", x, y),
+    }
+}
+
+/// Cheapest turn by `quarters` counter-clockwise quarter turns.
+fn quarter_turn(quarters: usize) -> Option<Instruction> {
+    match quarters % 4 {
+        0 => None,
+        1 => Some(Instruction {
+            action: Action::Left,
+            units: 90,
+        }),
+        2 => Some(Instruction {
+            action: Action::Left,
+            units: 180,
+        }),
+        _ => Some(Instruction {
+            action: Action::Right,
+            units: 90,
+        }),
+    }
+}
+
+fn east_west(dx: i64) -> Option<Instruction> {
+    match dx {
+        0 => None,
+        dx if dx > 0 => Some(Instruction {
+            action: Action::East,
+            units: dx as usize,
+        }),
+        dx => Some(Instruction {
+            action: Action::West,
+            units: dx.unsigned_abs() as usize,
+        }),
+    }
+}
+
+fn north_south(dy: i64) -> Option<Instruction> {
+    match dy {
+        0 => None,
+        dy if dy > 0 => Some(Instruction {
+            action: Action::North,
+            units: dy as usize,
+        }),
+        dy => Some(Instruction {
+            action: Action::South,
+            units: dy.unsigned_abs() as usize,
+        }),
+    }
+}
+
+fn forward(steps: i64) -> Instruction {
+    Instruction {
+        action: Action::Forward,
+        units: steps as usize,
+    }
+}
+
+/// The `k >= 1` with `k * (wx, wy) == (dx, dy)`, if any.
+fn multiple((dx, dy): Point, (wx, wy): Point) -> Option<i64> {
+    let k = match (wx, wy) {
+        (0, 0) => return None,
+        (0, wy) if dx == 0 && dy % wy == 0 => dy / wy,
+        (wx, _) if wx != 0 && dx % wx == 0 => dx / wx,
+        _ => return None,
+    };
+    if k >= 1 && k * wx == dx && k * wy == dy {
+        Some(k)
+    } else {
+        None
+    }
+}
+
+/// Waypoint component for each of `steps` forward moves so that they sum up to `target`.
+///
+/// Starting from `start`, the component only ever moves towards the target, which makes the
+/// largest adjustment (and thereby its cost) as small as possible. Returned as pieces of
+/// `(first step, value)`.
+fn axis_schedule(start: i64, steps: i64, target: i64) -> (u64, Vec<(i64, i64)>) {
+    let excess = target - steps * start;
+    if excess == 0 {
+        return (0, vec![(0, start)]);
+    }
+    let adjust = excess.unsigned_abs().div_ceil(steps as u64);
+    let full = (excess.unsigned_abs() / adjust) as i64;
+    let rest = (excess.unsigned_abs() % adjust) as i64;
+    let sign = excess.signum();
+
+    let mut rv = vec![(0, start)];
+    if rest > 0 {
+        rv.push((steps - full - 1, start + sign * rest));
+    }
+    rv.push((steps - full, start + sign * adjust as i64));
+    (adjust, rv)
+}
+
+fn value_at(schedule: &[(i64, i64)], step: i64) -> i64 {
+    schedule
+        .iter()
+        .rev()
+        .find(|(from, _)| *from <= step)
+        .map(|(_, value)| *value)
+        .unwrap_or(0)
+}
+
+/// Fewest instructions moving a ship with `waypoint` by `delta`.
+///
+/// Only forward moves change the position, so one instruction suffices if the waypoint already
+/// points at the target and two if a single turn or waypoint move makes it so. Otherwise the
+/// waypoint is moved onto the target and followed once.
+fn plan_waypoint_fewest(delta: Point, waypoint: Point) -> Vec<Instruction> {
+    let (dx, dy) = delta;
+    let (wx, wy) = waypoint;
+    if delta == (0, 0) {
+        return Vec::new();
+    }
+    for quarters in 0..4 {
+        if let Some(k) = multiple(delta, rotate_quarters(waypoint, quarters as i64)) {
+            return quarter_turn(quarters)
+                .into_iter()
+                .chain(Some(forward(k)))
+                .collect();
+        }
+    }
+    if wy != 0 && dy % wy == 0 && dy / wy > 0 && dx % (dy / wy) == 0 {
+        let k = dy / wy;
+        return vec![east_west(dx / k - wx).unwrap(), forward(k)];
+    }
+    if wx != 0 && dx % wx == 0 && dx / wx > 0 && dy % (dx / wx) == 0 {
+        let k = dx / wx;
+        return vec![north_south(dy / k - wy).unwrap(), forward(k)];
+    }
+    east_west(dx - wx)
+        .into_iter()
+        .chain(north_south(dy - wy))
+        .chain(Some(forward(1)))
+        .collect()
+}
+
+/// Fewest units moving a ship with `waypoint` by `delta`, turning only before the first move.
+///
+/// For a fixed number of forward steps, both waypoint components can be scheduled
+/// independently (see `axis_schedule`), so it suffices to try all step counts below the best
+/// cost found so far.
+fn plan_waypoint_straight(delta: Point, waypoint: Point) -> Vec<Instruction> {
+    let (dx, dy) = delta;
+    if delta == (0, 0) {
+        return Vec::new();
+    }
+    let mut best: Option<(u64, usize, i64)> = None;
+    for quarters in 0..4 {
+        let turn = quarter_turn(quarters).map_or(0, |t| t.units as u64);
+        let (wx, wy) = rotate_quarters(waypoint, quarters as i64);
+        let mut steps = 1;
+        while best.is_none_or(|(cost, _, _)| turn + (steps as u64) < cost) {
+            let cost = turn
+                + steps as u64
+                + axis_schedule(wx, steps, dx).0
+                + axis_schedule(wy, steps, dy).0;
+            if best.is_none_or(|(best, _, _)| cost < best) {
+                best = Some((cost, quarters, steps));
+            }
+            steps += 1;
+        }
+    }
+    let (_, quarters, steps) = best.unwrap();
+
+    let (mut wx, mut wy) = rotate_quarters(waypoint, quarters as i64);
+    let (_, xs) = axis_schedule(wx, steps, dx);
+    let (_, ys) = axis_schedule(wy, steps, dy);
+    let mut changes: Vec<_> = xs.iter().chain(ys.iter()).map(|(from, _)| *from).collect();
+    changes.push(steps);
+    changes.sort_unstable();
+    changes.dedup();
+
+    let mut rv: Vec<_> = quarter_turn(quarters).into_iter().collect();
+    for span in changes.windows(2) {
+        let (x, y) = (value_at(&xs, span[0]), value_at(&ys, span[0]));
+        rv.extend(east_west(x - wx));
+        rv.extend(north_south(y - wy));
+        rv.push(forward(span[1] - span[0]));
+        wx = x;
+        wy = y;
+    }
+    rv
+}
+
+/// Search states expanded by `plan_waypoint_units` before giving up.
+const MAX_EXPANDED: usize = 10_000_000;
+
+/// L1 distance of `p` to the convex polygon with counter-clockwise `vertices`.
+fn polygon_distance(p: Vector<f64>, vertices: &[Vector<f64>]) -> f64 {
+    let n = vertices.len();
+    let cross =
+        |a: Vector<f64>, b: Vector<f64>| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    if n >= 3 && (0..n).all(|i| cross(vertices[i], vertices[(i + 1) % n]) >= 0.0) {
+        return 0.0;
+    }
+    let segment = |a: Vector<f64>, b: Vector<f64>| {
+        // piecewise linear in t with kinks where a component vanishes
+        let at = |t: f64| (p.0 - a.0 - t * (b.0 - a.0)).abs() + (p.1 - a.1 - t * (b.1 - a.1)).abs();
+        [
+            0.0,
+            1.0,
+            (p.0 - a.0) / (b.0 - a.0),
+            (p.1 - a.1) / (b.1 - a.1),
+        ]
+        .iter()
+        .filter(|t| t.is_finite())
+        .map(|t| at(t.clamp(0.0, 1.0)))
+        .fold(f64::INFINITY, f64::min)
+    };
+    (0..n)
+        .map(|i| segment(vertices[i], vertices[(i + 1) % n]))
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Cheapest turns visiting all orientations in `mask` (bit `r` for `r` counter-clockwise
+/// quarter turns), starting at orientation 0.
+fn visiting_cost(mask: usize) -> u64 {
+    fn visit(at: usize, left: usize) -> u64 {
+        (0..4)
+            .filter(|r| left & (1 << r) != 0)
+            .map(|r| {
+                let d = (r + 4 - at) % 4;
+                90 * d.min(4 - d) as u64 + visit(r, left & !(1 << r))
+            })
+            .min()
+            .unwrap_or(0)
+    }
+    visit(0, mask & !1)
+}
+
+/// Lower bound on the units of any plan moving by `rest` with `waypoint`, in at most `limit`
+/// forward steps.
+///
+/// Let `T` forward steps use the orientations `O` (relative to now), summing up to `z`, and
+/// the waypoint be moved by `e` in total. Each move changes the covered distance by at most
+/// `T` times its size, so `|rest - z * waypoint| <= T * e` (as complex numbers). With `q = z *
+/// waypoint / T` in the convex hull of the rotated waypoints, the cost is at least
+/// `turns(O) + T + |rest / T - q|`. Minimizing over `q` and `1 / T` is convex.
+fn turning_bound(rest: Point, waypoint: Point, limit: u64) -> u64 {
+    let rest = (rest.0 as f64, rest.1 as f64);
+    let rotations: Vec<Vector<f64>> = (0..4)
+        .map(|r| {
+            let (x, y) = rotate_quarters(waypoint, r);
+            (x as f64, y as f64)
+        })
+        .collect();
+    let norm = (waypoint.0.abs() + waypoint.1.abs()) as f64;
+    let distance = rest.0.abs() + rest.1.abs();
+
+    let mut best = f64::INFINITY;
+    for mask in 1..16 {
+        let turns = visiting_cost(mask) as f64;
+        // no hull point is longer than the waypoint
+        if turns + any_bound(distance, norm) >= best {
+            continue;
+        }
+        let hull: Vec<_> = (0..4)
+            .filter(|r| mask & (1 << r) != 0)
+            .map(|r| rotations[r])
+            .collect();
+        let cost = |u: f64| 1.0 / u + polygon_distance((rest.0 * u, rest.1 * u), &hull);
+        // golden section search for u = 1 / T in [1 / limit, 1]
+        let (mut lo, mut hi) = (1.0 / limit.max(1) as f64, 1.0);
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        let mut minimum = cost(lo).min(cost(hi));
+        for _ in 0..60 {
+            let (a, b) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+            let (ca, cb) = (cost(a), cost(b));
+            minimum = minimum.min(ca).min(cb);
+            if ca < cb {
+                hi = b;
+            } else {
+                lo = a;
+            }
+        }
+        // the true minimum lies in [lo, hi], the slope is bounded by limit^2 + distance
+        let slack = (hi - lo) * ((limit * limit) as f64 + distance) + 1e-6;
+        best = best.min(turns + minimum - slack);
+    }
+    best.ceil().max(1.0) as u64
+}
+
+/// Lower bound of `min_T T + max(0, distance / T - norm)` over real `T >= 1`.
+fn any_bound(distance: f64, norm: f64) -> f64 {
+    if distance <= norm {
+        1.0
+    } else if norm * norm <= distance {
+        2.0 * distance.sqrt() - norm
+    } else {
+        distance / norm
+    }
+}
+
+/// Fewest units moving a ship with `waypoint` by `delta`.
+///
+/// A* over (remaining offset, waypoint) with unit-sized waypoint moves, single forward steps
+/// and turns, merging repeated actions afterwards. The best plan turning only at the start
+/// bounds the search from above, `turning_bound` from below.
+fn plan_waypoint_units(delta: Point, waypoint: Point) -> Result<Vec<Instruction>> {
+    type State = (Point, Point);
+    let straight = plan_waypoint_straight(delta, waypoint);
+    let upper: u64 = straight.iter().map(|i| i.units as u64).sum();
+
+    let heuristic = |(rest, w): State| -> u64 {
+        if rest == (0, 0) {
+            0
+        } else {
+            turning_bound(rest, w, upper)
+        }
+    };
+    let moves: Vec<Instruction> = ["N1", "S1", "E1", "W1", "F1", "L90", "R90", "L180"]
+        .iter()
+        .map(|l| Instruction::parser(l).unwrap().1)
+        .collect();
+    let step = |(rest, w): State, inst: &Instruction| -> State {
+        if let Some((dx, dy)) = cardinal(&inst.action) {
+            (rest, (w.0 + dx, w.1 + dy))
+        } else if let Some(angle) = turn_angle(inst) {
+            (rest, rotate_quarters(w, angle / 90))
+        } else {
+            ((rest.0 - w.0, rest.1 - w.1), w)
+        }
+    };
+
+    let start = (delta, waypoint);
+    let mut best: HashMap<State, (u64, Option<(State, usize)>)> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    best.insert(start, (0, None));
+    queue.push(Reverse((heuristic(start), Reverse(0), start)));
+    let mut expanded = 0;
+    while let Some(Reverse((_, Reverse(cost), state))) = queue.pop() {
+        if best[&state].0 < cost {
+            continue;
+        }
+        if state.0 == (0, 0) {
+            let mut rv: Vec<Instruction> = Vec::new();
+            let mut current = state;
+            while let Some((previous, idx)) = best[&current].1 {
+                match rv.last_mut() {
+                    Some(last) if last.action == moves[idx].action => {
+                        last.units += moves[idx].units
+                    }
+                    _ => rv.push(moves[idx].clone()),
+                }
+                current = previous;
+            }
+            rv.reverse();
+            return Ok(rv);
+        }
+        expanded += 1;
+        ensure!(
+            expanded <= MAX_EXPANDED,
+            "No shortest plan found within {} search states.",
+            MAX_EXPANDED
+        );
+        for (idx, inst) in moves.iter().enumerate() {
+            let next = step(state, inst);
+            let next_cost = cost + inst.units as u64;
+            if best.get(&next).is_some_and(|(c, _)| *c <= next_cost) {
+                continue;
+            }
+            let estimate = next_cost + heuristic(next);
+            // only strictly better plans are of interest
+            if estimate >= upper {
+                continue;
+            }
+            best.insert(next, (next_cost, Some((state, idx))));
+            queue.push(Reverse((estimate, Reverse(next_cost), next)));
+        }
+    }
+    // nothing beats the straight plan
+    Ok(straight)
+}
+
+/// Offset of a single step for the cardinal actions.
+fn cardinal(action: &Action) -> Option<Point> {
+    match action {
+        Action::North => Some((0, 1)),
+        Action::South => Some((0, -1)),
+        Action::East => Some((1, 0)),
+        Action::West => Some((-1, 0)),
+        _ => None,
+    }
+}
+
+/// `v + k * w`
+fn add_scaled<T: Coordinate>((vx, vy): Vector<T>, k: T, (wx, wy): Vector<T>) -> Vector<T> {
+    (vx + k * wx, vy + k * wy)
+}
+
+/// Counter-clockwise angle of a turn instruction.
+fn turn_angle(inst: &Instruction) -> Option<i64> {
+    match inst.action {
+        Action::Left => Some(inst.units as i64),
+        Action::Right => Some(-(inst.units as i64)),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -104,7 +710,7 @@ impl Instruction {
     pub fn read_from(input: &Path) -> Result<Vec<Self>> {
         let mut rv = Vec::new();
 
-        for line in io::BufReader::new(File::open(&input)?).lines() {
+        for line in io::BufReader::new(File::open(input)?).lines() {
             let line = line?;
             match Instruction::parser(&line).finish() {
                 Ok((_, instruction)) => {
@@ -117,151 +723,394 @@ impl Instruction {
     }
 }
 
-struct Ship {
-    x: i64,
-    y: i64,
-    facing: Direction,
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.action, self.units)
+    }
+}
+
+/// SVG y axis points south; avoids printing "-0".
+fn flip(y: f64) -> f64 {
+    0.0 - y
+}
+
+/// Everything a ship went through, recorded after each instruction.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Trajectory<T> {
+    positions: Vec<Vector<T>>,
+    // waypoint relative to the ship (only for navigation by waypoint)
+    waypoints: Vec<Vector<T>>,
 }
 
-impl Ship {
+impl<T: Coordinate> Trajectory<T> {
+    /// Draw the path, waypoint vectors and start/end markers; north is up.
+    pub fn to_svg(&self) -> String {
+        let floats = |v: &Vec<Vector<T>>| -> Vec<Vector<f64>> {
+            v.iter().map(|(x, y)| (x.to_f64(), y.to_f64())).collect()
+        };
+        let (positions, waypoints) = (floats(&self.positions), floats(&self.waypoints));
+        let points = positions.iter().cloned().chain(
+            positions
+                .iter()
+                .zip(waypoints.iter())
+                .map(|((x, y), (wx, wy))| (x + wx, y + wy)),
+        );
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+        for (x, y) in points {
+            min_x = min_x.min(x);
+            max_x = max_x.max(x);
+            min_y = min_y.min(y);
+            max_y = max_y.max(y);
+        }
+        let extent = (max_x - min_x).max(max_y - min_y).max(1.0);
+        let margin = (extent / 20.0).floor() + 1.0;
+        let radius = extent / 100.0;
+
+        let mut rv = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_x - margin,
+            flip(max_y) - margin,
+            max_x - min_x + 2.0 * margin,
+            max_y - min_y + 2.0 * margin
+        );
+        for ((x, y), (wx, wy)) in positions.iter().zip(waypoints.iter()) {
+            rv.push_str(&format!(
+                "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"orange\" \
+                 vector-effect=\"non-scaling-stroke\"/>\n",
+                x,
+                flip(*y),
+                x + wx,
+                flip(y + wy)
+            ));
+        }
+        let path: Vec<_> = positions
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, flip(*y)))
+            .collect();
+        rv.push_str(&format!(
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"navy\" \
+             vector-effect=\"non-scaling-stroke\"/>\n",
+            path.join(" ")
+        ));
+        if let (Some((sx, sy)), Some((ex, ey))) = (positions.first(), positions.last()) {
+            rv.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"green\"/>\n",
+                sx,
+                flip(*sy),
+                radius
+            ));
+            rv.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>\n",
+                ex,
+                flip(*ey),
+                radius
+            ));
+        }
+        rv.push_str("</svg>\n");
+        rv
+    }
+}
+
+#[derive(Clone)]
+struct Ship<T> {
+    position: Vector<T>,
+    facing: Vector<T>,
+    trajectory: Trajectory<T>,
+}
+
+impl<T: Coordinate> Ship<T> {
     pub fn new() -> Self {
+        let origin = (T::from_i64(0), T::from_i64(0));
         Self {
-            x: 0,
-            y: 0,
-            facing: Direction::East,
+            position: origin,
+            facing: (T::from_i64(1), T::from_i64(0)),
+            trajectory: Trajectory {
+                positions: vec![origin],
+                waypoints: Vec::new(),
+            },
         }
     }
+}
 
-    pub fn run(&mut self, inst: &Instruction) {
-        use Action::*;
-        match inst.action {
-            North => {
-                self.y += inst.units as i64;
-            }
-            South => {
-                self.y -= inst.units as i64;
-            }
-            East => {
-                self.x += inst.units as i64;
-            }
-            West => {
-                self.x -= inst.units as i64;
-            }
-            Forward => match self.facing {
-                Direction::North => {
-                    self.y += inst.units as i64;
-                }
-                Direction::South => {
-                    self.y -= inst.units as i64;
-                }
-                Direction::East => {
-                    self.x += inst.units as i64;
-                }
-                Direction::West => {
-                    self.x -= inst.units as i64;
-                }
-            },
-            Right => {
-                let turns = inst.units / 90;
-                for _ in 0..turns {
-                    self.facing = match self.facing {
-                        Direction::North => Direction::East,
-                        Direction::South => Direction::West,
-                        Direction::East => Direction::South,
-                        Direction::West => Direction::North,
-                    }
-                }
+impl<T: Coordinate> Navigator<T> for Ship<T> {
+    /// Turning never pays off, so go straight along each axis, following the current heading
+    /// where it matches.
+    fn plan(&self, target: Point, _cost: Cost) -> Result<Vec<Instruction>> {
+        let (x, y) = integral(self.position)?;
+        let (dx, dy) = (target.0 - x, target.1 - y);
+        let heading = |inst: Instruction, (ax, ay): Point| {
+            if self.facing == (T::from_i64(ax), T::from_i64(ay)) {
+                forward(inst.units as i64)
+            } else {
+                inst
             }
-            Left => {
-                let turns = inst.units / 90;
-                for _ in 0..turns {
-                    self.facing = match self.facing {
-                        Direction::North => Direction::West,
-                        Direction::South => Direction::East,
-                        Direction::East => Direction::North,
-                        Direction::West => Direction::South,
-                    }
-                }
-            }
-        }
+        };
+        Ok(east_west(dx)
+            .map(|inst| heading(inst, (dx.signum(), 0)))
+            .into_iter()
+            .chain(north_south(dy).map(|inst| heading(inst, (0, dy.signum()))))
+            .collect())
     }
 
-    pub fn run_all(&mut self, insts: &[Instruction]) {
-        for inst in insts.iter() {
-            self.run(inst);
+    fn run(&mut self, inst: &Instruction) -> Result<()> {
+        let units = T::from_i64(inst.units as i64);
+        if turn_angle(inst).is_some() {
+            self.facing = T::rotate(self.facing, inst)?;
+        } else {
+            let direction = match cardinal(&inst.action) {
+                Some((dx, dy)) => (T::from_i64(dx), T::from_i64(dy)),
+                None => self.facing,
+            };
+            self.position = add_scaled(self.position, units, direction);
         }
+        self.trajectory.positions.push(self.position);
+        Ok(())
+    }
+
+    fn position(&self) -> Vector<T> {
+        self.position
     }
 
-    pub fn manhattan(&self) -> usize {
-        self.x.abs() as usize + self.y.abs() as usize
+    fn trajectory(&self) -> &Trajectory<T> {
+        &self.trajectory
     }
 }
 
-struct ShipV2 {
-    x: i64,
-    y: i64,
-    waypoint_x: i64,
-    waypoint_y: i64,
+#[derive(Clone)]
+struct ShipV2<T> {
+    position: Vector<T>,
+    waypoint: Vector<T>, // relative to the ship
+    trajectory: Trajectory<T>,
 }
 
-impl ShipV2 {
+impl<T: Coordinate> ShipV2<T> {
     pub fn new() -> Self {
+        let origin = (T::from_i64(0), T::from_i64(0));
+        let waypoint = (T::from_i64(10), T::from_i64(1));
         Self {
-            x: 0,
-            y: 0,
-            waypoint_x: 10,
-            waypoint_y: 1,
+            position: origin,
+            waypoint,
+            trajectory: Trajectory {
+                positions: vec![origin],
+                waypoints: vec![waypoint],
+            },
         }
     }
+}
 
-    pub fn run(&mut self, inst: &Instruction) {
-        use Action::*;
-        let units = inst.units as i64;
-        match inst.action {
-            North => {
-                self.waypoint_y += units;
-            }
-            South => {
-                self.waypoint_y -= units;
-            }
-            East => {
-                self.waypoint_x += units;
-            }
-            West => {
-                self.waypoint_x -= units;
-            }
-            Forward => {
-                self.x += units * self.waypoint_x;
-                self.y += units * self.waypoint_y;
+impl<T: Coordinate> Navigator<T> for ShipV2<T> {
+    fn plan(&self, target: Point, cost: Cost) -> Result<Vec<Instruction>> {
+        let (x, y) = integral(self.position)?;
+        let waypoint = integral(self.waypoint)?;
+        let delta = (target.0 - x, target.1 - y);
+        match cost {
+            Cost::Instructions => Ok(plan_waypoint_fewest(delta, waypoint)),
+            Cost::Units => plan_waypoint_units(delta, waypoint),
+        }
+    }
+
+    fn run(&mut self, inst: &Instruction) -> Result<()> {
+        let units = T::from_i64(inst.units as i64);
+        if turn_angle(inst).is_some() {
+            self.waypoint = T::rotate(self.waypoint, inst)?;
+        } else if let Some((dx, dy)) = cardinal(&inst.action) {
+            let direction = (T::from_i64(dx), T::from_i64(dy));
+            self.waypoint = add_scaled(self.waypoint, units, direction);
+        } else {
+            self.position = add_scaled(self.position, units, self.waypoint);
+        }
+        self.trajectory.positions.push(self.position);
+        self.trajectory.waypoints.push(self.waypoint);
+        Ok(())
+    }
+
+    fn position(&self) -> Vector<T> {
+        self.position
+    }
+
+    fn trajectory(&self) -> &Trajectory<T> {
+        &self.trajectory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::Entry;
+    use std::collections::{HashMap, VecDeque};
+
+    fn example() -> Vec<Instruction> {
+        ["F10", "N3", "F7", "R90", "F11"]
+            .iter()
+            .map(|l| Instruction::parser(l).unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn test_trajectory() -> Result<()> {
+        let mut ship = Ship::<i64>::new();
+        ship.run_all(&example())?;
+        assert_eq!(ship.manhattan(), 25);
+        assert_eq!(
+            ship.trajectory.positions,
+            vec![(0, 0), (10, 0), (10, 3), (17, 3), (17, 3), (17, -8)]
+        );
+        assert!(ship.trajectory.waypoints.is_empty());
+
+        let mut ship = ShipV2::<i64>::new();
+        ship.run_all(&example())?;
+        assert_eq!(ship.manhattan(), 286);
+        assert_eq!(
+            ship.trajectory.positions,
+            vec![
+                (0, 0),
+                (100, 10),
+                (100, 10),
+                (170, 38),
+                (170, 38),
+                (214, -72)
+            ]
+        );
+        assert_eq!(
+            ship.trajectory.waypoints,
+            vec![(10, 1), (10, 1), (10, 4), (10, 4), (4, -10), (4, -10)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_turning() -> Result<()> {
+        let parse = |l: &str| Instruction::parser(l).unwrap().1;
+
+        let mut ship = Ship::<i64>::new();
+        assert!(ship.run(&parse("L45")).is_err());
+        ship.run(&parse("L450"))?;
+        ship.run(&parse("F2"))?;
+        assert_eq!(ship.position(), (0, 2));
+
+        let mut ship = Ship::<f64>::new();
+        ship.run_all(&[parse("L60"), parse("F10"), parse("R150"), parse("F10")])?;
+        let (x, y) = ship.position();
+        assert!((x - 5.0).abs() < 1e-9);
+        assert!((y - (5.0 * 3f64.sqrt() - 10.0)).abs() < 1e-9);
+
+        let mut ship = ShipV2::<f64>::new();
+        ship.run_all(&[parse("R45"), parse("L45"), parse("R270"), parse("F1")])?;
+        let (x, y) = ship.position();
+        assert!((x + 1.0).abs() < 1e-9 && (y - 10.0).abs() < 1e-9);
+        let err = ShipV2::<i64>::new().run(&parse("R1")).unwrap_err();
+        assert_eq!(err.to_string(), "R1: only multiples of 90 are supported");
+        Ok(())
+    }
+
+    fn show(plan: &[Instruction]) -> String {
+        let plan: Vec<_> = plan.iter().map(|i| i.to_string()).collect();
+        plan.join(" ")
+    }
+
+    #[test]
+    fn test_plan_fewest() -> Result<()> {
+        let mut ship = Ship::<i64>::new();
+        ship.run_all(&example())?;
+        let plan = ship.plan((0, 0), Cost::Instructions)?;
+        assert_eq!(show(&plan), "W17 N8");
+        ship.run_all(&plan)?;
+        assert_eq!(ship.position(), (0, 0));
+        assert_eq!(show(&ship.plan((-4, -3), Cost::Units)?), "W4 F3");
+
+        let ship = ShipV2::<i64>::new();
+        for (target, expected) in [
+            ((0, 0), ""),
+            ((20, 2), "F2"),
+            ((-10, -1), "L180 F1"),
+            ((30, 6), "W5 F6"),
+            ((7, 3), "W3 N2 F1"),
+        ] {
+            let plan = ship.plan(target, Cost::Instructions)?;
+            assert_eq!(show(&plan), expected);
+            let mut check = ship.clone();
+            check.run_all(&plan)?;
+            assert_eq!(check.position(), target);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_units() -> Result<()> {
+        // cheapest plans by brute force: all unit-sized moves without turns (which cost more
+        // than any plan within reach, so this is the optimum among all plans)
+        let start = ((0, 0), (2, 1));
+        let mut cost = HashMap::new();
+        let mut queue = VecDeque::new();
+        cost.insert(start, 0);
+        queue.push_back(start);
+        while let Some(state) = queue.pop_front() {
+            let ((x, y), (wx, wy)) = state;
+            let c = cost[&state];
+            if c == 12 {
+                continue;
             }
-            Right => {
-                let turns = inst.units / 90;
-                for _ in 0..turns {
-                    let old_x = self.waypoint_x;
-                    let old_y = self.waypoint_y;
-                    self.waypoint_x = old_y;
-                    self.waypoint_y = -old_x;
+            for next in [
+                ((x + wx, y + wy), (wx, wy)),
+                ((x, y), (wx + 1, wy)),
+                ((x, y), (wx - 1, wy)),
+                ((x, y), (wx, wy + 1)),
+                ((x, y), (wx, wy - 1)),
+            ] {
+                if let Entry::Vacant(e) = cost.entry(next) {
+                    e.insert(c + 1);
+                    queue.push_back(next);
                 }
             }
-            Left => {
-                let turns = inst.units / 90;
-                for _ in 0..turns {
-                    let old_x = self.waypoint_x;
-                    let old_y = self.waypoint_y;
-                    self.waypoint_x = -old_y;
-                    self.waypoint_y = old_x;
-                }
+        }
+
+        let mut ship = ShipV2::<i64>::new();
+        ship.waypoint = (2, 1);
+        for tx in -4..=4 {
+            for ty in -4..=4 {
+                let plan = ship.plan((tx, ty), Cost::Units)?;
+                let units: usize = plan.iter().map(|i| i.units).sum();
+                let best = cost
+                    .iter()
+                    .filter(|((pos, _), _)| *pos == (tx, ty))
+                    .map(|(_, c)| *c)
+                    .min()
+                    .unwrap();
+                assert_eq!(units, best, "plan to ({}, {}): {}", tx, ty, show(&plan));
+                let mut check = ship.clone();
+                check.run_all(&plan)?;
+                assert_eq!(check.position(), (tx, ty));
             }
         }
-    }
 
-    pub fn run_all(&mut self, insts: &[Instruction]) {
-        for inst in insts.iter() {
-            self.run(inst);
+        // turning beats moving a long waypoint around, also between forward moves
+        for (waypoint, target, expected) in [
+            ((1000, 0), (-2000, 0), "L180 F2"),
+            ((1000, 0), (0, -1000), "R90 F1"),
+            ((1000, 0), (1000, 1000), "F1 L90 F1"),
+            ((1000, 0), (1000, -1000), "F1 R90 F1"),
+            ((50, 0), (50, 50), "N50 F1"),
+        ] {
+            ship.waypoint = waypoint;
+            let plan = ship.plan(target, Cost::Units)?;
+            assert_eq!(show(&plan), expected);
+            let mut check = ship.clone();
+            check.run_all(&plan)?;
+            assert_eq!(check.position(), target);
         }
+        Ok(())
     }
 
-    pub fn manhattan(&self) -> usize {
-        self.x.abs() as usize + self.y.abs() as usize
+    #[test]
+    fn test_svg() -> Result<()> {
+        let mut ship = ShipV2::<i64>::new();
+        ship.run_all(&example())?;
+        let svg = ship.trajectory.to_svg();
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-11 -53 240 146\">"));
+        assert!(svg.contains("points=\"0,0 100,-10 100,-10 170,-38 170,-38 214,72\""));
+        assert_eq!(svg.matches("<line ").count(), 6);
+        assert_eq!(svg.matches("<circle ").count(), 2);
+        Ok(())
     }
 }
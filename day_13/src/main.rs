@@ -1,43 +1,81 @@
 use anyhow::{bail, Context, Result};
-use nom::{
-    branch::alt,
-    character::complete::{char, digit1},
-    Finish, IResult,
-};
-use std::collections::HashMap;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
     let busses = Busses::read_from(&input)?;
-    part1(&busses);
+    let time = |idx: usize, usage: &str| -> Result<usize> {
+        args.get(idx)
+            .with_context(|| usage.to_string())?
+            .parse()
+            .with_context(|| format!("Invalid time: {}", args[idx]))
+    };
 
-    assert_eq!(Schedule::from("17,x,13,19")?.find_first_matching(), 3417);
-    assert_eq!(Schedule::from("67,7,59,61")?.find_first_matching(), 754018);
-    assert_eq!(
-        Schedule::from("67,x,7,59,61")?.find_first_matching(),
-        779210
-    );
-    assert_eq!(
-        Schedule::from("67,7,x,59,61")?.find_first_matching(),
-        1261476
-    );
-    assert_eq!(
-        Schedule::from("1789,37,47,1889")?.find_first_matching(),
-        1202161486
-    );
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            part1(&busses);
 
-    let sched = Schedule::read_from(&input)?;
-    part2(&sched);
+            for (schedule, expected) in [
+                ("17,x,13,19", 3417),
+                ("67,7,59,61", 754018),
+                ("67,x,7,59,61", 779210),
+                ("67,7,x,59,61", 1261476),
+                ("1789,37,47,1889", 1202161486),
+            ] {
+                assert_eq!(
+                    Schedule::from(schedule)?.solve()?.timestamp,
+                    BigInt::from(expected)
+                );
+            }
+
+            let sched = Schedule::read_from(&input)?;
+            part2(&sched)?;
+        }
+        Some("departures") => {
+            let usage = "Usage: departures <time> [<count>]";
+            let count = match args.get(3) {
+                Some(count) => count.parse()?,
+                None => 10,
+            };
+            for departure in busses.departures(time(2, usage)?).take(count) {
+                println!("{}", departure);
+            }
+        }
+        Some("matching") => {
+            let usage = "Usage: matching <from> <to> [<bus>...]";
+            let (from, to) = (time(2, usage)?, time(3, usage)?);
+            let ids = args[4..]
+                .iter()
+                .map(|id| id.parse().with_context(|| format!("Invalid bus: {}", id)))
+                .collect::<Result<Vec<usize>>>()?;
+            let sched = Schedule::read_from(&input)?;
+            for timestamp in sched.matching(&ids, from, to)? {
+                println!("{}", timestamp);
+            }
+        }
+        Some("gap") => {
+            let usage = "Usage: gap <from> <to>";
+            let (start, end) = busses.longest_gap(time(2, usage)?, time(3, usage)?);
+            println!(
+                "{} minutes without departures from {} to {}",
+                end - start,
+                start,
+                end
+            );
+        }
+        Some(cmd) => bail!("Unknown command: {}", cmd),
+    }
 
     Ok(())
 }
@@ -51,9 +89,13 @@ fn part1(busses: &Busses) {
     println!("(part1) Answer: {}", bus * ttw);
 }
 
-fn part2(sched: &Schedule) {
-    let timestep = sched.find_first_matching();
-    println!("(part2) first timestep found: {}", timestep)
+fn part2(sched: &Schedule) -> Result<()> {
+    let solution = sched.solve()?;
+    println!(
+        "(part2) first timestep found: {} (repeating every {})",
+        solution.timestamp, solution.period
+    );
+    Ok(())
 }
 
 struct Busses {
@@ -63,7 +105,7 @@ struct Busses {
 
 impl Busses {
     pub fn read_from(input: &Path) -> Result<Self> {
-        let mut lines = io::BufReader::new(File::open(&input)?).lines();
+        let mut lines = io::BufReader::new(File::open(input)?).lines();
         let starttime = lines.next().unwrap()?.parse()?;
 
         let line_busses = lines
@@ -71,7 +113,7 @@ impl Busses {
             .with_context(|| "No line with bus information provided.")??;
 
         let mut busses = Vec::new();
-        for potential_bus in line_busses.split(",") {
+        for potential_bus in line_busses.split(',') {
             match potential_bus {
                 "x" => { /* skip */ }
                 id => {
@@ -96,21 +138,88 @@ impl Busses {
             let wait_time = bus - self.starttime % bus;
             if wait_time < time_to_wait {
                 time_to_wait = wait_time;
-                next_bus = bus.clone();
+                next_bus = *bus;
             }
         }
 
         (next_bus, time_to_wait)
     }
+
+    /// All departures at or after `time`, ordered by time and then bus.
+    pub fn departures(&self, time: usize) -> Departures {
+        Departures {
+            queue: self
+                .busses
+                .iter()
+                .map(|bus| {
+                    Reverse(Departure {
+                        time: time.div_ceil(*bus) * bus,
+                        bus: *bus,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Longest stretch `(start, end)` within `from..=to` during which no bus departs.
+    ///
+    /// The window bounds count as departures.
+    pub fn longest_gap(&self, from: usize, to: usize) -> (usize, usize) {
+        let mut last = from;
+        let mut longest = (from, from);
+        let times = self
+            .departures(from)
+            .map(|d| d.time)
+            .take_while(|t| *t <= to)
+            .chain(Some(to.max(from)));
+        for time in times {
+            if time - last > longest.1 - longest.0 {
+                longest = (last, time);
+            }
+            last = time;
+        }
+        longest
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Departure {
+    time: usize,
+    bus: usize,
+}
+
+impl fmt::Display for Departure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: bus {}", self.time, self.bus)
+    }
+}
+
+/// Endless merge of the timetables of all busses.
+struct Departures {
+    queue: BinaryHeap<Reverse<Departure>>,
+}
+
+impl Iterator for Departures {
+    type Item = Departure;
+
+    fn next(&mut self) -> Option<Departure> {
+        let Reverse(departure) = self.queue.pop()?;
+        self.queue.push(Reverse(Departure {
+            time: departure.time + departure.bus,
+            bus: departure.bus,
+        }));
+        Some(departure)
+    }
 }
 
 struct Schedule {
-    bus_to_offset: HashMap<usize, usize>,
+    // (bus id, offset) in order of appearance, ids may repeat
+    busses: Vec<(usize, usize)>,
 }
 
 impl Schedule {
     pub fn read_from(input: &Path) -> Result<Self> {
-        let mut lines = io::BufReader::new(File::open(&input)?).lines();
+        let mut lines = io::BufReader::new(File::open(input)?).lines();
         lines.next(); // first line ignored
 
         let line_busses = lines
@@ -120,59 +229,274 @@ impl Schedule {
     }
 
     pub fn from(input: &str) -> Result<Self> {
-        let mut bus_to_offset = HashMap::new();
+        let mut busses = Vec::new();
 
-        for (offset, potential_bus) in input.split(",").enumerate() {
+        for (offset, potential_bus) in input.split(',').enumerate() {
             match potential_bus {
                 "x" => {}
                 id => {
-                    bus_to_offset.insert(
-                        id.parse()
-                            .with_context(|| format!("Invalid bus line: {}", id))?,
-                        offset,
-                    );
+                    let id = id
+                        .parse()
+                        .ok()
+                        .filter(|id| *id > 0)
+                        .with_context(|| format!("Invalid bus line: {}", id))?;
+                    busses.push((id, offset));
                 }
             }
         }
 
-        Ok(Self { bus_to_offset })
+        Ok(Self { busses })
     }
 
-    pub fn find_first_matching(&self) -> usize {
-        let busses = self.busses_sorted_reversed();
-        let largest = busses[0];
-        let offset_largest = self.bus_to_offset[&largest] as i64;
-
-        let bus_to_relat_offset: HashMap<usize, i64> = self
-            .bus_to_offset
+    /// Bus `id` departing `offset` minutes after `t` means `t + offset = 0 (mod id)`.
+    pub fn congruences(&self) -> Vec<Congruence> {
+        self.busses
             .iter()
-            .map(|(k, v)| (*k, *v as i64 - offset_largest as i64))
-            .collect();
-
-        let mut timestep: i64 = 0;
-        let mut to_add = largest as i64;
-        let mut idx_done = 1;
-        'a: loop {
-            timestep += to_add;
-            eprint!("\rChecking: {}", timestep);
-            for bus in &busses[idx_done..] {
-                if (timestep + bus_to_relat_offset[bus]) % *bus as i64 == 0 {
-                    idx_done += 1;
-                    to_add *= *bus as i64;
-                } else {
-                    continue 'a;
+            .map(|(id, offset)| {
+                let modulus = BigInt::from(*id);
+                Congruence {
+                    residue: (-BigInt::from(*offset)).mod_floor(&modulus),
+                    modulus,
                 }
-            }
+            })
+            .collect()
+    }
+
+    pub fn solve(&self) -> Result<Solution, Box<Conflict>> {
+        solve(&self.congruences())
+    }
+
+    /// Only the given busses (all if empty), keeping their offsets.
+    pub fn restricted(&self, ids: &[usize]) -> Result<Self> {
+        if let Some(missing) = ids
+            .iter()
+            .find(|id| !self.busses.iter().any(|b| b.0 == **id))
+        {
+            bail!("Bus {} is not part of the schedule.", missing);
+        }
+        Ok(Self {
+            busses: self
+                .busses
+                .iter()
+                .filter(|(id, _)| ids.is_empty() || ids.contains(id))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// All timestamps in `from..=to` at which the given busses (all if empty) depart in the
+    /// pattern of the schedule.
+    pub fn matching(&self, ids: &[usize], from: usize, to: usize) -> Result<Vec<BigInt>> {
+        let solution = self.restricted(ids)?.solve()?;
+        let (from, to) = (BigInt::from(from), BigInt::from(to));
+        let skip = (&from - &solution.timestamp).div_ceil(&solution.period);
+        let mut timestamp = solution.timestamp + skip.max(BigInt::zero()) * &solution.period;
+
+        let mut rv = Vec::new();
+        while timestamp <= to {
+            rv.push(timestamp.clone());
+            timestamp += &solution.period;
+        }
+        Ok(rv)
+    }
+}
+
+/// `t = residue (mod modulus)`
+#[derive(Clone, Debug, PartialEq)]
+struct Congruence {
+    residue: BigInt,
+    modulus: BigInt,
+}
+
+impl fmt::Display for Congruence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "t = {} (mod {})", self.residue, self.modulus)
+    }
+}
+
+/// All timestamps `timestamp + k * period` for `k >= 0`, `timestamp` being the smallest.
+#[derive(Clone, Debug, PartialEq)]
+struct Solution {
+    timestamp: BigInt,
+    period: BigInt,
+}
+
+/// Proof that a system of congruences has no solution: two of them require different
+/// residues modulo the gcd of their moduli.
+#[derive(Clone, Debug, PartialEq)]
+struct Conflict {
+    first: Congruence,
+    second: Congruence,
+    gcd: BigInt,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "No solution: {} and {} disagree modulo {}.",
+            self.first, self.second, self.gcd
+        )
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// Chinese remainder theorem for arbitrary (not necessarily coprime) moduli.
+///
+/// Congruences are merged one by one via the extended Euclidean algorithm. A system is solvable
+/// iff all pairs are, so if merging fails, the offending congruence conflicts with one of the
+/// earlier ones.
+fn solve(congruences: &[Congruence]) -> Result<Solution, Box<Conflict>> {
+    let mut residue = BigInt::zero();
+    let mut modulus = BigInt::one();
 
-            eprint!("\r");
-            return (timestep - offset_largest) as usize;
+    for (idx, current) in congruences.iter().enumerate() {
+        let gcd = modulus.extended_gcd(&current.modulus);
+        let diff = &current.residue - &residue;
+        if !diff.is_multiple_of(&gcd.gcd) {
+            return Err(congruences[..idx]
+                .iter()
+                .find_map(|earlier| {
+                    let gcd = earlier.modulus.gcd(&current.modulus);
+                    if (&earlier.residue - &current.residue).is_multiple_of(&gcd) {
+                        None
+                    } else {
+                        Some(Box::new(Conflict {
+                            first: earlier.clone(),
+                            second: current.clone(),
+                            gcd,
+                        }))
+                    }
+                })
+                .expect("pairwise consistent systems are solvable"));
         }
+        let lcm = &modulus / &gcd.gcd * &current.modulus;
+        residue = (&residue + &diff / &gcd.gcd * &gcd.x * &modulus).mod_floor(&lcm);
+        modulus = lcm;
     }
 
-    fn busses_sorted_reversed(&self) -> Vec<usize> {
-        let mut rv: Vec<_> = self.bus_to_offset.keys().cloned().collect();
-        rv.sort();
-        rv.reverse();
-        rv
+    Ok(Solution {
+        timestamp: residue,
+        period: modulus,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn congruence(residue: i64, modulus: i64) -> Congruence {
+        Congruence {
+            residue: residue.into(),
+            modulus: modulus.into(),
+        }
+    }
+
+    #[test]
+    fn test_departures() {
+        let busses = Busses {
+            starttime: 939,
+            busses: vec![7, 13, 19, 31, 59],
+        };
+        let next: Vec<_> = busses.departures(939).take(4).collect();
+        assert_eq!(
+            next,
+            vec![
+                Departure { time: 944, bus: 59 },
+                Departure { time: 945, bus: 7 },
+                Departure { time: 949, bus: 13 },
+                Departure { time: 950, bus: 19 },
+            ]
+        );
+        assert_eq!(
+            busses.departures(0).next(),
+            Some(Departure { time: 0, bus: 7 })
+        );
+        // 931 (7, 19), 936 (13), 938 (7), end of window
+        assert_eq!(busses.longest_gap(931, 940), (931, 936));
+        assert_eq!(busses.longest_gap(939, 939), (939, 939));
+    }
+
+    #[test]
+    fn test_matching() {
+        let schedule = Schedule::from("7,13,x,x,59,x,31,19").unwrap();
+        let period = 7 * 13 * 59 * 31 * 19;
+        assert_eq!(
+            schedule.matching(&[], 0, 3 * period).unwrap(),
+            vec![
+                BigInt::from(1068781),
+                BigInt::from(1068781 + period),
+                BigInt::from(1068781 + 2 * period)
+            ]
+        );
+
+        // 7 at t and 13 at t + 1
+        assert_eq!(
+            schedule.matching(&[13, 7], 70, 200).unwrap(),
+            vec![BigInt::from(77), BigInt::from(168)]
+        );
+        assert!(schedule.matching(&[5], 0, 100).is_err());
+    }
+
+    #[test]
+    fn test_example() {
+        let solution = Schedule::from("7,13,x,x,59,x,31,19")
+            .unwrap()
+            .solve()
+            .unwrap();
+        assert_eq!(solution.timestamp, BigInt::from(1068781));
+        assert_eq!(solution.period, BigInt::from(7 * 13 * 59 * 31 * 19));
+    }
+
+    #[test]
+    fn test_non_coprime() {
+        // t = 3 (mod 4), t = 5 (mod 6) and t = 1 (mod 10) -> 11 (mod 60)
+        let solution = solve(&[congruence(3, 4), congruence(5, 6), congruence(1, 10)]).unwrap();
+        assert_eq!(solution.timestamp, BigInt::from(11));
+        assert_eq!(solution.period, BigInt::from(60));
+
+        let solution = Schedule::from("4,x,6").unwrap().solve().unwrap();
+        assert_eq!(solution.timestamp, BigInt::from(4));
+        assert_eq!(solution.period, BigInt::from(12));
+
+        assert_eq!(
+            solve(&[]).unwrap(),
+            Solution {
+                timestamp: BigInt::zero(),
+                period: BigInt::one()
+            }
+        );
+    }
+
+    #[test]
+    fn test_conflict() {
+        let conflict = solve(&[congruence(1, 9), congruence(3, 4), congruence(2, 6)]).unwrap_err();
+        assert_eq!(conflict.first, congruence(1, 9));
+        assert_eq!(conflict.second, congruence(2, 6));
+        assert_eq!(conflict.gcd, BigInt::from(3));
+
+        // the same bus cannot depart at two different offsets
+        let conflict = Schedule::from("5,5").unwrap().solve().unwrap_err();
+        assert_eq!(
+            conflict.to_string(),
+            "No solution: t = 0 (mod 5) and t = 4 (mod 5) disagree modulo 5."
+        );
+    }
+
+    #[test]
+    fn test_big() {
+        // period exceeds u64
+        let primes = "1000000007,x,998244353,1000000009,x,x,2147483647,4294967291";
+        let schedule = Schedule::from(primes).unwrap();
+        let solution = schedule.solve().unwrap();
+        assert!(solution.period > BigInt::from(u64::MAX));
+        for congruence in schedule.congruences() {
+            assert_eq!(
+                solution.timestamp.mod_floor(&congruence.modulus),
+                congruence.residue
+            );
+        }
+        assert!(solution.timestamp < solution.period);
     }
 }
@@ -8,29 +8,171 @@ use nom::{
 };
 use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fmt;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let options = Options::from_args(env::args().skip(1))?;
 
-    let program = Program::read_from(&input)?;
+    let program = Program::read_from(&options.input, options.width)?;
 
     // println!("Read: {:#?}", program);
 
-    part1(&program);
-    part2(&program);
+    match options.command {
+        Command::Solve => {
+            part1(&program);
+            part2(&program)?;
+        }
+        Command::Dump { decoder, format } => {
+            print!("{}", program.execute(decoder)?.dump(format));
+        }
+        Command::Explain {
+            decoder,
+            format,
+            address,
+        } => {
+            let memory = program.execute(decoder)?;
+            print!("{}", program.explain(&memory, address, format));
+        }
+    }
 
     Ok(())
 }
 
-const BITWIDTH: usize = 36;
+const DEFAULT_BITWIDTH: usize = 36;
+const MAX_BITWIDTH: usize = 64;
+/// The v2 decoder writes 2^floating addresses per assignment, so cap the floating bits.
+const MAX_FLOATING: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+enum Decoder {
+    V1,
+    V2,
+}
+
+#[derive(Debug)]
+enum Command {
+    Solve,
+    Dump {
+        decoder: Decoder,
+        format: Format,
+    },
+    Explain {
+        decoder: Decoder,
+        format: Format,
+        address: usize,
+    },
+}
+
+#[derive(Debug)]
+struct Options {
+    input: PathBuf,
+    width: usize,
+    command: Command,
+}
+
+impl Options {
+    /// Usage: `day_14 <input> [--width <bits>] [dump|explain --addr <address>] [--v1|--v2]
+    /// [--dec|--hex|--bin]`
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let input = PathBuf::from(args.next().with_context(|| "No input provided!")?);
+        let mut width = DEFAULT_BITWIDTH;
+        let mut command = Command::Solve;
+        let mut address_given = true;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => {
+                    width = args
+                        .next()
+                        .with_context(|| "--width requires a value")?
+                        .parse()
+                        .with_context(|| "Invalid value for --width")?;
+                }
+                "dump" => {
+                    command = Command::Dump {
+                        decoder: Decoder::V1,
+                        format: Format::Dec,
+                    };
+                }
+                "explain" => {
+                    command = Command::Explain {
+                        decoder: Decoder::V1,
+                        format: Format::Dec,
+                        address: 0,
+                    };
+                    address_given = false;
+                }
+                "--addr" => match &mut command {
+                    Command::Explain { address, .. } => {
+                        *address = parse_address(
+                            &args.next().with_context(|| "--addr requires a value")?,
+                        )?;
+                        address_given = true;
+                    }
+                    _ => bail!("{} is only valid for explain", arg),
+                },
+                "--v1" | "--v2" => match &mut command {
+                    Command::Dump { decoder, .. } | Command::Explain { decoder, .. } => {
+                        *decoder = if arg == "--v1" {
+                            Decoder::V1
+                        } else {
+                            Decoder::V2
+                        };
+                    }
+                    _ => bail!("{} is only valid for dump/explain", arg),
+                },
+                "--dec" | "--hex" | "--bin" => match &mut command {
+                    Command::Dump { format, .. } | Command::Explain { format, .. } => {
+                        *format = match arg.as_str() {
+                            "--dec" => Format::Dec,
+                            "--hex" => Format::Hex,
+                            _ => Format::Bin,
+                        };
+                    }
+                    _ => bail!("{} is only valid for dump/explain", arg),
+                },
+                _ => bail!("Unknown argument: {}", arg),
+            }
+        }
+
+        if !address_given {
+            bail!("explain requires --addr <address>");
+        }
+
+        if width == 0 || width > MAX_BITWIDTH {
+            bail!("Word width must be in 1..={}, got {}", MAX_BITWIDTH, width);
+        }
+
+        Ok(Self {
+            input,
+            width,
+            command,
+        })
+    }
+}
+
+/// Parse an address given in decimal or with a `0x`/`0b` prefix.
+fn parse_address(s: &str) -> Result<usize> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        usize::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        usize::from_str_radix(bin, 2)
+    } else {
+        s.parse()
+    };
+    parsed.with_context(|| format!("Invalid address: {}", s))
+}
+
+/// All bits set for a word of the given width.
+fn word_mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
 
 #[derive(Clone, Debug)]
 struct Bitmask {
@@ -38,18 +180,21 @@ struct Bitmask {
     bitmask_0_inv: u64,
     bitmask_1: u64,
 
-    floating: Vec<usize>, // floating bits, LSB is 0, MSB is BITWIDTH
+    floating: Vec<usize>, // floating bits, LSB is 0, MSB is width - 1
+
+    width: usize,
 }
 
 impl Bitmask {
-    pub fn empty() -> Bitmask {
-        let bitmask_0_inv = (1 << BITWIDTH) - 1;
+    pub fn empty(width: usize) -> Bitmask {
+        let bitmask_0_inv = word_mask(width);
         let bitmask_1 = 0;
 
         Self {
             bitmask_1,
             bitmask_0_inv,
             floating: Vec::new(),
+            width,
         }
     }
 
@@ -65,14 +210,16 @@ impl Bitmask {
         let mut bitmask_1 = 0;
         let mut floating = Vec::new();
 
+        let width = mask.len();
+
         for (i, m) in mask.iter().enumerate() {
-            bitmask_0_inv = bitmask_0_inv << 1;
-            bitmask_1 = bitmask_1 << 1;
+            bitmask_0_inv <<= 1;
+            bitmask_1 <<= 1;
 
             match m {
                 'X' => {
                     bitmask_0_inv += 1;
-                    floating.push(BITWIDTH - 1 - i);
+                    floating.push(width - 1 - i);
                 }
                 '0' => {}
                 '1' => {
@@ -91,6 +238,7 @@ impl Bitmask {
                 bitmask_1,
                 bitmask_0_inv,
                 floating,
+                width,
             },
         ))
     }
@@ -100,17 +248,17 @@ impl Bitmask {
 
         let base = base | self.bitmask_1 as usize;
 
-        for count in 0..(1 << self.floating.len()) {
+        for count in 0..(1usize << self.floating.len()) {
             let mut current = base;
             for (count_idx, float_idx) in self.floating.iter().enumerate() {
                 // delete bit at float_idx in current
-                if current & (1 << float_idx) > 0 {
-                    current -= 1 << float_idx;
+                if current & (1usize << float_idx) > 0 {
+                    current -= 1usize << float_idx;
                 }
 
                 // write bit at count_idx from count to float_idx in current
-                if count & (1 << count_idx) > 0 {
-                    current += 1 << float_idx;
+                if count & (1usize << count_idx) > 0 {
+                    current += 1usize << float_idx;
                 }
             }
             rv.push(current);
@@ -120,6 +268,22 @@ impl Bitmask {
     }
 }
 
+impl fmt::Display for Bitmask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bit in (0..self.width).rev() {
+            let c = if self.bitmask_1 & (1 << bit) > 0 {
+                '1'
+            } else if self.bitmask_0_inv & (1 << bit) > 0 {
+                'X'
+            } else {
+                '0'
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct Assignment {
     address: usize,
@@ -174,15 +338,30 @@ impl Instruction {
 #[derive(Debug)]
 struct Program {
     instructions: Vec<Instruction>,
+    width: usize,
 }
 
 impl Program {
-    pub fn read_from(input: &Path) -> Result<Self> {
+    pub fn read_from(input: &Path, width: usize) -> Result<Self> {
+        Self::parse(&read_to_string(input)?, width)
+    }
+
+    pub fn parse(content: &str, width: usize) -> Result<Self> {
         let mut instructions = Vec::new();
 
-        for line in io::BufReader::new(File::open(&input)?).lines() {
-            match Instruction::parse(&line?).finish() {
+        for (lineno, line) in content.lines().enumerate() {
+            match Instruction::parse(line).finish() {
                 Ok((_, instr)) => {
+                    if let Instruction::Mask(mask) = &instr {
+                        if mask.width != width {
+                            bail!(
+                                "Mask in line {} has {} bits, expected {}",
+                                lineno + 1,
+                                mask.width,
+                                width
+                            );
+                        }
+                    }
                     instructions.push(instr);
                 }
                 Err(e) => {
@@ -191,23 +370,44 @@ impl Program {
             }
         }
 
-        Ok(Self { instructions })
+        Ok(Self {
+            instructions,
+            width,
+        })
+    }
+
+    pub fn execute(&self, decoder: Decoder) -> Result<Memory> {
+        match decoder {
+            Decoder::V1 => Ok(self.run()),
+            Decoder::V2 => self.run_v2(),
+        }
     }
 
-    pub fn run(&self) -> HashMap<usize, u64> {
+    pub fn run(&self) -> Memory {
         use Instruction::*;
-        let mut memory = HashMap::new();
+        let mut memory = Memory::new(self.width);
 
-        let mut mask = Bitmask::empty();
+        let mut mask = Bitmask::empty(self.width);
+        let mut mask_idx = None;
 
-        for instr in self.instructions.iter() {
+        for (idx, instr) in self.instructions.iter().enumerate() {
             match instr {
                 Mem(assign) => {
                     let value = assign.value & mask.bitmask_0_inv | mask.bitmask_1;
-                    memory.insert(assign.address, value);
+                    memory.write(
+                        assign.address,
+                        Write {
+                            instruction: idx,
+                            mask: mask_idx,
+                            address: assign.address,
+                            raw_value: assign.value,
+                            value,
+                        },
+                    );
                 }
                 Mask(m) => {
                     mask = m.clone();
+                    mask_idx = Some(idx);
                 }
             }
         }
@@ -215,27 +415,155 @@ impl Program {
         memory
     }
 
-    pub fn run_v2(&self) -> HashMap<usize, u64> {
+    pub fn run_v2(&self) -> Result<Memory> {
         use Instruction::*;
-        let mut memory = HashMap::new();
+        let mut memory = Memory::new(self.width);
 
-        let mut mask = Bitmask::empty();
+        let mut mask = Bitmask::empty(self.width);
+        let mut mask_idx = None;
 
-        for instr in self.instructions.iter() {
+        for (idx, instr) in self.instructions.iter().enumerate() {
             match instr {
                 Mem(assign) => {
-                    for address in mask.generate_addresses(assign.address)
-                    {
-                        memory.insert(address, assign.value);
+                    for address in mask.generate_addresses(assign.address) {
+                        memory.write(
+                            address,
+                            Write {
+                                instruction: idx,
+                                mask: mask_idx,
+                                address: assign.address,
+                                raw_value: assign.value,
+                                value: assign.value,
+                            },
+                        );
                     }
                 }
                 Mask(m) => {
+                    if m.floating.len() > MAX_FLOATING {
+                        bail!(
+                            "Mask in instruction {} has {} floating bits, at most {} are supported",
+                            idx + 1,
+                            m.floating.len(),
+                            MAX_FLOATING
+                        );
+                    }
                     mask = m.clone();
+                    mask_idx = Some(idx);
                 }
             }
         }
 
-        memory
+        Ok(memory)
+    }
+
+    /// The mask in effect for a write, i.e. the referenced mask instruction or the empty mask.
+    pub fn mask_of(&self, write: &Write) -> Bitmask {
+        match write.mask.map(|idx| &self.instructions[idx]) {
+            Some(Instruction::Mask(m)) => m.clone(),
+            _ => Bitmask::empty(self.width),
+        }
+    }
+
+    /// Describe every write that ended up at `address`, oldest first.
+    pub fn explain(&self, memory: &Memory, address: usize, format: Format) -> String {
+        let history = memory.history(address);
+        let mut rv = format!(
+            "mem[{}]: {} write(s)\n",
+            memory.format_word(address as u64, format),
+            history.len()
+        );
+        for write in history {
+            rv.push_str(&format!(
+                "  instruction {}: mem[{}] = {} with mask {} -> {}\n",
+                write.instruction,
+                memory.format_word(write.address as u64, format),
+                memory.format_word(write.raw_value, format),
+                self.mask_of(write),
+                memory.format_word(write.value, format),
+            ));
+        }
+        rv
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Dec,
+    Hex,
+    Bin,
+}
+
+/// Provenance of a single write to a memory cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Write {
+    instruction: usize,  // index of the `mem` instruction
+    mask: Option<usize>, // index of the mask instruction in effect, if any
+    address: usize,      // address as given in the instruction
+    raw_value: u64,      // value as given in the instruction
+    value: u64,          // value actually stored
+}
+
+#[derive(Debug)]
+struct Memory {
+    cells: HashMap<usize, Vec<Write>>, // all writes per address, oldest first
+    width: usize,
+}
+
+impl Memory {
+    pub fn new(width: usize) -> Self {
+        Self {
+            cells: HashMap::new(),
+            width,
+        }
+    }
+
+    pub fn write(&mut self, address: usize, write: Write) {
+        self.cells.entry(address).or_default().push(write);
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = u64> + '_ {
+        self.cells
+            .values()
+            .filter_map(|h| h.last())
+            .map(|w| w.value)
+    }
+
+    /// All writes to `address`, oldest first.
+    pub fn history(&self, address: usize) -> &[Write] {
+        self.cells.get(&address).map_or(&[], |h| h.as_slice())
+    }
+
+    /// Last write of all cells sorted by address.
+    pub fn sorted(&self) -> Vec<(usize, &Write)> {
+        let mut cells: Vec<_> = self
+            .cells
+            .iter()
+            .filter_map(|(a, h)| h.last().map(|w| (*a, w)))
+            .collect();
+        cells.sort_by_key(|(a, _)| *a);
+        cells
+    }
+
+    pub fn format_word(&self, word: u64, format: Format) -> String {
+        match format {
+            Format::Dec => format!("{}", word),
+            Format::Hex => format!("{:#0w$x}", word, w = self.width.div_ceil(4) + 2),
+            Format::Bin => format!("{:#0w$b}", word, w = self.width + 2),
+        }
+    }
+
+    /// One line per cell: address, value and the instruction that last wrote it.
+    pub fn dump(&self, format: Format) -> String {
+        let mut rv = String::new();
+        for (address, write) in self.sorted() {
+            rv.push_str(&format!(
+                "mem[{}] = {} (instruction {})\n",
+                self.format_word(address as u64, format),
+                self.format_word(write.value, format),
+                write.instruction
+            ));
+        }
+        rv
     }
 }
 
@@ -246,9 +574,102 @@ fn part1(prog: &Program) {
     println!("(part1) Sum of all elements: {}", sum);
 }
 
-fn part2(prog: &Program) {
-    let mem = prog.run_v2();
+fn part2(prog: &Program) -> Result<()> {
+    let mem = prog.run_v2()?;
     let sum: u64 = mem.values().sum();
 
     println!("(part2) Sum of all elements: {}", sum);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X
+mem[8] = 11
+mem[7] = 101
+mem[8] = 0";
+
+    #[test]
+    fn test_width_validation() {
+        assert!(Program::parse(EXAMPLE, 36).is_ok());
+        assert!(Program::parse(EXAMPLE, 32).is_err());
+        assert!(Program::parse("mask = 1X0\nmem[1] = 7", 3).is_ok());
+    }
+
+    #[test]
+    fn test_dump() -> Result<()> {
+        let memory = Program::parse(EXAMPLE, 36)?.run();
+        assert_eq!(memory.values().sum::<u64>(), 165);
+        assert_eq!(
+            memory.dump(Format::Dec),
+            "mem[7] = 101 (instruction 2)\nmem[8] = 64 (instruction 3)\n"
+        );
+        assert_eq!(
+            memory.dump(Format::Hex).lines().next(),
+            Some("mem[0x000000007] = 0x000000065 (instruction 2)")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let program = Program::parse(EXAMPLE, 36)?;
+        let memory = program.run();
+        let history = memory.history(8);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].raw_value, 11);
+        assert_eq!(history[0].value, 73);
+        assert_eq!(history[1].instruction, 3);
+        assert_eq!(history[1].mask, Some(0));
+        assert_eq!(
+            program.mask_of(&history[1]).to_string(),
+            "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X"
+        );
+        assert!(memory.history(9).is_empty());
+
+        let program = Program::parse(
+            "mask = 000000000000000000000000000000X1001X
+mem[42] = 100
+mask = 00000000000000000000000000000000X0XX
+mem[26] = 1",
+            36,
+        )?;
+        let memory = program.run_v2()?;
+        let history = memory.history(0x1a);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].address, 42);
+        assert_eq!(history[1].address, 26);
+        assert_eq!(history[1].value, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_address() -> Result<()> {
+        assert_eq!(parse_address("0x1A")?, 26);
+        assert_eq!(parse_address("0b11010")?, 26);
+        assert_eq!(parse_address("26")?, 26);
+        assert!(parse_address("0xZZ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_width() -> Result<()> {
+        let mask = format!("mask = 1{}", "X".repeat(63));
+        let memory = Program::parse(&format!("{}\nmem[0] = 1", mask), 64)?.run();
+        assert_eq!(memory.values().next(), Some((1 << 63) + 1));
+
+        // floating bits in the upper half of a 64 bit word
+        let mask = format!("mask = X{}X{}", "0".repeat(30), "0".repeat(32));
+        let memory = Program::parse(&format!("{}\nmem[0] = 7", mask), 64)?.run_v2()?;
+        let addresses: Vec<_> = memory.sorted().iter().map(|(a, _)| *a).collect();
+        assert_eq!(addresses, vec![0, 1 << 32, 1 << 63, (1 << 63) | (1 << 32)]);
+        assert!(
+            Program::parse(&format!("{}\nmem[0] = 1", mask.replace('0', "X")), 64)?
+                .run_v2()
+                .is_err()
+        );
+        Ok(())
+    }
 }
@@ -1,8 +1,51 @@
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 
-fn main() {
-    let input: Vec<usize> = vec![8, 13, 1, 0, 18, 9];
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
 
+    let start = match get_flag(&args, "--start") {
+        Some(s) => parse_start(s)?,
+        None => PUZZLE_INPUT.to_vec(),
+    };
+
+    match args.first().map(|s| s.as_str()) {
+        None | Some("--start") => solve(&start),
+        Some("play") => {
+            let turns = parse_turns(args.get(1))?;
+            let mut game = Game::with_capacity(&start, turns);
+            finish(&mut game, turns, get_flag(&args, "--save"))
+        }
+        Some("analyze") => {
+            let turns = parse_turns(args.get(1))?;
+            let analysis = Analysis::run(&start, turns);
+            let csv = match get_flag(&args, "--stat").map(|s| s.as_str()) {
+                None | Some("summary") => analysis.summary_csv(),
+                Some("first") => analysis.first_occurrences_csv(),
+                Some("gaps") => analysis.gaps_csv(),
+                Some("records") => analysis.records_csv(),
+                Some("max") => analysis.max_csv(),
+                Some(stat) => bail!("Unknown statistic: {}", stat),
+            };
+            print!("{}", csv);
+            Ok(())
+        }
+        Some("resume") => {
+            let path = args.get(1).with_context(|| "No state file provided!")?;
+            let turns = parse_turns(args.get(2))?;
+            let mut game = Game::load(BufReader::new(File::open(path)?))?;
+            finish(&mut game, turns, get_flag(&args, "--save"))
+        }
+        Some(cmd) => bail!("Unknown command: {}", cmd),
+    }
+}
+
+const PUZZLE_INPUT: [usize; 6] = [8, 13, 1, 0, 18, 9];
+
+fn solve(input: &[usize]) -> Result<()> {
     assert_eq!(play(&[0, 3, 6], 2020), 436);
     assert_eq!(play(&[1, 3, 2], 2020), 1);
     assert_eq!(play(&[2, 1, 3], 2020), 10);
@@ -11,7 +54,7 @@ fn main() {
     assert_eq!(play(&[3, 2, 1], 2020), 438);
     assert_eq!(play(&[3, 1, 2], 2020), 1836);
 
-    println!("(part1) The 2020th number is {}", play(&input[..], 2020));
+    println!("(part1) The 2020th number is {}", play(input, 2020));
 
     assert_eq!(play(&[0, 3, 6], 30000000), 175594);
     assert_eq!(play(&[1, 3, 2], 30000000), 2578);
@@ -21,127 +64,439 @@ fn main() {
     assert_eq!(play(&[3, 2, 1], 30000000), 18);
     assert_eq!(play(&[3, 1, 2], 30000000), 362);
 
-    println!(
-        "(part2) The 2020th number is {}",
-        play(&input[..], 30000000)
-    );
+    println!("(part2) The 30000000th number is {}", play(input, 30000000));
+
+    Ok(())
 }
 
-fn play_v1(input: &[usize], num_turns: usize) -> usize {
-    let mut track: HashMap<usize, VecDeque<usize>> = HashMap::new();
+/// Play until `turns`, print the number spoken then and optionally save the state.
+fn finish(game: &mut Game, turns: usize, save: Option<&String>) -> Result<()> {
+    if turns < game.turn() {
+        bail!(
+            "Game is already at turn {}, cannot go back to {}",
+            game.turn(),
+            turns
+        );
+    }
+    let num = game.play_until(turns);
+    println!("The {}th number is {}", turns, num);
 
-    for (turn, num) in input.iter().enumerate() {
-        let mut deque = VecDeque::new();
-        deque.push_front(turn);
-        track.insert(*num, deque);
+    if let Some(path) = save {
+        game.save(BufWriter::new(File::create(path)?))?;
+        println!("Saved state after turn {} to {}", game.turn(), path);
     }
+    Ok(())
+}
 
-    let mut last_num: usize = input[input.len() - 1];
+fn get_flag<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|idx| args.get(idx + 1))
+}
 
-    for turn in input.len()..num_turns {
-        if (turn + 1) % 1000000 == 0 {
-            println!("{}/{}", turn + 1, num_turns);
+fn parse_turns(arg: Option<&String>) -> Result<usize> {
+    let turns: usize = arg
+        .with_context(|| "No number of turns provided!")?
+        .parse()
+        .with_context(|| "Invalid number of turns")?;
+    if turns == 0 || turns > MAX_TURNS {
+        bail!("Number of turns must be in 1..={}", MAX_TURNS);
+    }
+    Ok(turns)
+}
+
+fn parse_start(arg: &str) -> Result<Vec<usize>> {
+    let start = arg
+        .split(',')
+        .map(|n| {
+            // numbers are stored as `u32`, larger ones would be truncated
+            n.trim()
+                .parse::<u32>()
+                .map(|n| n as usize)
+                .with_context(|| {
+                    format!(
+                        "Invalid starting number (must be in 0..={}): {}",
+                        u32::MAX,
+                        n
+                    )
+                })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if start.is_empty() {
+        bail!("No starting numbers provided!");
+    }
+    Ok(start)
+}
+
+/// Turns are stored as `u32`, with 0 meaning "never spoken".
+const MAX_TURNS: usize = u32::MAX as usize;
+
+const STATE_MAGIC: &[u8; 8] = b"DAY15ST1";
+
+/// Memory game engine: a dense table of the turn in which each number was last spoken.
+///
+/// Iterating yields the spoken numbers, starting with turn 1.
+#[derive(Clone, Debug, PartialEq)]
+struct Game {
+    start: Vec<u32>,
+    // last_seen[n] is the turn (1-based) in which n was spoken before the current turn
+    last_seen: Vec<u32>,
+    turn: u32, // number of turns played so far
+    last: u32, // number spoken in `turn`
+}
+
+impl Game {
+    /// Preallocate the table for a game of `turns` turns.
+    pub fn with_capacity(start: &[usize], turns: usize) -> Self {
+        let start: Vec<u32> = start.iter().map(|n| *n as u32).collect();
+        let size = start
+            .iter()
+            .map(|n| *n as usize + 1)
+            .max()
+            .unwrap_or(0)
+            .max(turns);
+        Self {
+            start,
+            last_seen: vec![0; size],
+            turn: 0,
+            last: 0,
         }
-        let current_num = match track.get(&last_num) {
-            None => 0,
-            Some(vd) => match vd.len() {
-                1 => 0,
-                2 => vd[0] - vd[1],
-                l => {
-                    panic!("Invalid deque len: {}", l);
-                }
-            },
-        };
+    }
+
+    /// Number of turns played so far.
+    pub fn turn(&self) -> usize {
+        self.turn as usize
+    }
+
+    /// Number spoken in the last turn played.
+    pub fn last_spoken(&self) -> Option<usize> {
+        if self.turn == 0 {
+            None
+        } else {
+            Some(self.last as usize)
+        }
+    }
 
-        match track.get_mut(&current_num) {
-            None => {
-                let mut vd = VecDeque::new();
-                vd.push_front(turn);
-                track.insert(current_num, vd);
+    /// Advance to `turn` and return the number spoken in it.
+    pub fn play_until(&mut self, turn: usize) -> usize {
+        assert!(turn >= self.turn(), "Cannot go back in time.");
+        let remaining = turn - self.turn();
+        if remaining == 0 {
+            return self.last_spoken().expect("Game has not started yet.");
+        }
+        self.nth(remaining - 1).expect("Turn limit exceeded.")
+    }
+
+    fn step(&mut self) -> Option<u32> {
+        if self.turn == u32::MAX {
+            return None;
+        }
+        let turn = self.turn + 1;
+
+        let num = if (turn as usize) <= self.start.len() {
+            self.start[turn as usize - 1]
+        } else {
+            match self.last_seen.get(self.last as usize) {
+                None | Some(0) => 0,
+                Some(prev) => turn - 1 - prev,
             }
-            Some(vd) => {
-                vd.push_front(turn);
-                vd.truncate(2);
+        };
+
+        if self.turn > 0 {
+            let idx = self.last as usize;
+            if idx >= self.last_seen.len() {
+                let size = (idx + 1).max(self.last_seen.len() * 2);
+                self.last_seen.resize(size, 0);
             }
+            self.last_seen[idx] = self.turn;
         }
 
-        last_num = current_num;
+        self.turn = turn;
+        self.last = num;
+        Some(num)
+    }
+
+    /// Serialise the current state.
+    pub fn save(&self, mut w: impl Write) -> io::Result<()> {
+        // only store the used part of the table
+        let used = self
+            .last_seen
+            .iter()
+            .rposition(|t| *t != 0)
+            .map_or(0, |idx| idx + 1);
+
+        w.write_all(STATE_MAGIC)?;
+        for v in [self.turn, self.last, self.start.len() as u32, used as u32].iter() {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        for v in self.start.iter().chain(self.last_seen[..used].iter()) {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    /// Restore a state written by [`Game::save`].
+    pub fn load(mut r: impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            bail!("Not a day 15 state file.");
+        }
+
+        let mut read_u32 = || -> Result<u32> {
+            let mut buf = [0; 4];
+            r.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        };
+
+        let turn = read_u32()?;
+        let last = read_u32()?;
+        let num_start = read_u32()?;
+        let used = read_u32()?;
+
+        let start = (0..num_start)
+            .map(|_| read_u32())
+            .collect::<Result<Vec<_>>>()?;
+        let last_seen = (0..used).map(|_| read_u32()).collect::<Result<Vec<_>>>()?;
+
+        if start.is_empty() {
+            bail!("State file contains no starting numbers.");
+        }
+
+        Ok(Self {
+            start,
+            last_seen,
+            turn,
+            last,
+        })
     }
-    last_num
 }
 
-fn advance(track: &mut BTreeMap<usize, usize>) {
-    for v in track.values_mut() {
-        *v += 1;
+impl Iterator for Game {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        self.step().map(|n| n as usize)
     }
 }
 
-fn play_v2(input: &[usize], num_turns: usize) -> usize {
-    let mut track: BTreeMap<usize, usize> = BTreeMap::new();
+fn play(input: &[usize], num_turns: usize) -> usize {
+    Game::with_capacity(input, num_turns).play_until(num_turns)
+}
+
+/// Statistics over the first turns of a game.
+#[derive(Debug)]
+struct Analysis {
+    start: Vec<usize>,
+    turns: usize,
+    // first_seen[n] is the turn in which n was spoken first, 0 if never
+    first_seen: Vec<u32>,
+    // how often each age was computed by the game rule
+    gaps: BTreeMap<usize, usize>,
+    zeros: usize,
+    // (turn, value) whenever a new largest value was spoken
+    records: Vec<(usize, usize)>,
+}
+
+impl Analysis {
+    /// Play `turns` turns starting with `start` and collect statistics on the way.
+    pub fn run(start: &[usize], turns: usize) -> Self {
+        let mut first_seen = vec![0; start.iter().max().map_or(0, |m| m + 1).max(turns)];
+        let mut gaps = BTreeMap::new();
+        let mut zeros = 0;
+        let mut records: Vec<(usize, usize)> = Vec::new();
+
+        for (idx, num) in Game::with_capacity(start, turns).take(turns).enumerate() {
+            let turn = idx + 1;
 
-    for num in input.iter() {
-        advance(&mut track);
-        track.insert(*num, 0);
+            if first_seen[num] == 0 {
+                first_seen[num] = turn as u32;
+            }
+            if num == 0 {
+                zeros += 1;
+            }
+            // after the starting numbers, every non-zero number spoken is the age of the
+            // previous one
+            if turn > start.len() && num > 0 {
+                *gaps.entry(num).or_insert(0) += 1;
+            }
+            if records.last().is_none_or(|(_, max)| num > *max) {
+                records.push((turn, num));
+            }
+        }
+
+        Self {
+            start: start.to_vec(),
+            turns,
+            first_seen,
+            gaps,
+            zeros,
+            records,
+        }
     }
-    let mut seen_at: HashMap<BTreeMap<usize, usize>, usize> = HashMap::new();
 
-    let mut last: usize = input[input.len() - 1];
-    let mut turn = input.len();
+    /// All spoken numbers with the turn of their first occurrence, ordered by number.
+    pub fn first_occurrences(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.first_seen
+            .iter()
+            .enumerate()
+            .filter(|(_, turn)| **turn > 0)
+            .map(|(num, turn)| (num, *turn as usize))
+    }
 
-    let mut cycle_found = false;
+    pub fn distinct(&self) -> usize {
+        self.first_occurrences().count()
+    }
 
-    while turn < num_turns {
-        let num = track.get(&last).cloned().unwrap_or(0);
-        // println!("[{}/{}] {} -> {}", turn, num_turns, last, num);
-        // println!("Pre-advance: {:#?}", track);
-        advance(&mut track);
-        track.insert(last, 1);
-        // println!("Post-advance: {:#?}", track);
-        last = num;
+    pub fn zero_frequency(&self) -> f64 {
+        self.zeros as f64 / self.turns as f64
+    }
 
-        if !cycle_found {
-            match seen_at.get(&track) {
-                None => {
-                    seen_at.insert(track.clone(), turn);
-                }
-                Some(prev_turn) => {
-                    // we have found a previously visited state -> shorten
-                    let cycle_len = turn - prev_turn;
+    /// Largest number spoken up to (and including) `turn`.
+    pub fn max_up_to(&self, turn: usize) -> Option<usize> {
+        let idx = self.records.partition_point(|(t, _)| *t <= turn);
+        if idx == 0 {
+            None
+        } else {
+            Some(self.records[idx - 1].1)
+        }
+    }
+
+    pub fn summary_csv(&self) -> String {
+        let (max_turn, max) = self.records.last().cloned().unwrap_or((0, 0));
+        format!(
+            "start,turns,distinct,zeros,zero_frequency,max,max_turn\n\"{}\",{},{},{},{},{},{}\n",
+            self.start
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            self.turns,
+            self.distinct(),
+            self.zeros,
+            self.zero_frequency(),
+            max,
+            max_turn
+        )
+    }
 
-                    let remaining = num_turns - turn;
+    pub fn first_occurrences_csv(&self) -> String {
+        let mut rv = String::from("number,first_turn\n");
+        for (num, turn) in self.first_occurrences() {
+            rv.push_str(&format!("{},{}\n", num, turn));
+        }
+        rv
+    }
 
-                    turn += (remaining / cycle_len) * cycle_len;
-                    cycle_found = true;
-                }
+    pub fn gaps_csv(&self) -> String {
+        let mut rv = String::from("gap,count\n");
+        for (gap, count) in self.gaps.iter() {
+            rv.push_str(&format!("{},{}\n", gap, count));
+        }
+        rv
+    }
+
+    /// Largest number spoken up to every power of ten and the final turn.
+    pub fn max_csv(&self) -> String {
+        let mut rv = String::from("turn,max\n");
+        let checkpoints = std::iter::successors(Some(1usize), |t| t.checked_mul(10))
+            .take_while(|t| *t < self.turns)
+            .chain(std::iter::once(self.turns));
+        for turn in checkpoints {
+            if let Some(max) = self.max_up_to(turn) {
+                rv.push_str(&format!("{},{}\n", turn, max));
             }
         }
-        turn += 1;
+        rv
+    }
+
+    pub fn records_csv(&self) -> String {
+        let mut rv = String::from("turn,max\n");
+        for (turn, max) in self.records.iter() {
+            rv.push_str(&format!("{},{}\n", turn, max));
+        }
+        rv
     }
-    println!("{}", last);
-    last
 }
 
-fn play(input: &[usize], num_turns: usize) -> usize {
-    let mut track: HashMap<usize, usize> = HashMap::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for (turn, num) in input.iter().enumerate() {
-        track.insert(*num, turn);
+    #[test]
+    fn test_iterator() {
+        let spoken: Vec<_> = Game::with_capacity(&[0, 3, 6], 0).take(10).collect();
+        assert_eq!(spoken, vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
     }
 
-    let mut last_num: usize = input[input.len() - 1];
+    #[test]
+    fn test_play_until() {
+        let mut game = Game::with_capacity(&[0, 3, 6], 0);
+        assert_eq!(game.last_spoken(), None);
+        assert_eq!(game.play_until(2), 3);
+        assert_eq!(game.play_until(2), 3);
+        assert_eq!(game.play_until(2020), 436);
+        assert_eq!(game.turn(), 2020);
+    }
 
-    for turn in input.len()..num_turns {
-        if (turn + 1) % 10000000 == 0 {
-            println!("{}/{}", turn + 1, num_turns);
-        }
-        let current_num = match track.get(&last_num) {
-            None => 0,
-            Some(last_turn) => turn - last_turn - 1,
-        };
-        // println!("{}", current_num);
-        track.insert(last_num, turn - 1);
-        // println!("After turn #{}: {:#?}", turn, track);
-        last_num = current_num;
+    #[test]
+    fn test_analysis() {
+        // 0, 3, 6, 0, 3, 3, 1, 0, 4, 0
+        let analysis = Analysis::run(&[0, 3, 6], 10);
+        assert_eq!(
+            analysis.first_occurrences().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 7), (3, 2), (4, 9), (6, 3)]
+        );
+        assert_eq!(analysis.distinct(), 5);
+        assert_eq!(analysis.zeros, 4);
+        assert!((analysis.zero_frequency() - 0.4).abs() < 1e-9);
+        assert_eq!(
+            analysis
+                .gaps
+                .iter()
+                .map(|(g, c)| (*g, *c))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (3, 2), (4, 1)]
+        );
+        assert_eq!(analysis.records, vec![(1, 0), (2, 3), (3, 6)]);
+        assert_eq!(analysis.max_up_to(0), None);
+        assert_eq!(analysis.max_up_to(2), Some(3));
+        assert_eq!(analysis.max_up_to(10), Some(6));
+        assert_eq!(analysis.max_csv(), "turn,max\n1,0\n10,6\n");
+        assert_eq!(
+            analysis.summary_csv(),
+            "start,turns,distinct,zeros,zero_frequency,max,max_turn\n\"0,3,6\",10,5,4,0.4,6,3\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_start() -> Result<()> {
+        assert_eq!(parse_start("0, 3,6")?, vec![0, 3, 6]);
+        assert_eq!(parse_start("4294967295,0")?, vec![u32::MAX as usize, 0]);
+        assert!(parse_start("4294967296,0").is_err());
+        assert!(parse_start("1,-2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_resume() -> Result<()> {
+        let mut game = Game::with_capacity(&[1, 3, 2], 0);
+        game.play_until(1000);
+
+        let mut state = Vec::new();
+        game.save(&mut state)?;
+        let mut resumed = Game::load(&state[..])?;
+        assert_eq!(resumed.turn(), 1000);
+        assert_eq!(resumed.play_until(2020), 1);
+
+        // resume while still reading the starting numbers
+        let mut game = Game::with_capacity(&[1, 3, 2], 0);
+        game.play_until(2);
+        let mut state = Vec::new();
+        game.save(&mut state)?;
+        assert_eq!(Game::load(&state[..])?.play_until(2020), 1);
+
+        assert!(Game::load(&b"garbage"[..]).is_err());
+        Ok(())
     }
-    last_num
 }
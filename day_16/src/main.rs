@@ -1,29 +1,41 @@
 use anyhow::{bail, Context, Result};
 use nom::{
-    branch::alt,
-    bytes::complete::{is_a, is_not, tag, take_while1},
-    character::complete::{char, digit1, line_ending, multispace0, none_of, one_of},
+    bytes::complete::{is_a, is_not, tag},
+    character::complete::{char, digit1, line_ending, multispace0},
+    combinator::opt,
+    error::{Error, ErrorKind},
     multi::{many1, separated_list1},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    sequence::{preceded, terminated, tuple},
     Finish, IResult,
 };
-use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs::read_to_string;
+use std::fmt;
+use std::fs::{read_to_string, write};
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
     let notes = Notes::read_from(&input)?;
     // println!("Notes: {:#?}", notes);
-    part1(&notes);
-    part2(&notes)?;
+
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            part1(&notes);
+            part2(&notes)?;
+        }
+        Some("report") => {
+            for rejection in notes.rejections() {
+                println!("{}", rejection);
+            }
+        }
+        Some("csv") => {
+            let output = args.get(2).with_context(|| "Usage: csv <output>")?;
+            write(output, notes.to_csv()?)?;
+        }
+        Some(cmd) => bail!("Unknown command: {}", cmd),
+    }
 
     Ok(())
 }
@@ -51,6 +63,7 @@ fn part2(notes: &Notes) -> Result<()> {
     Ok(())
 }
 
+/// Inclusive range of values, `usize::MAX` as upper bound means open-ended.
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 struct Range {
     from: usize,
@@ -58,20 +71,25 @@ struct Range {
 }
 
 impl Range {
+    /// Either `from-to`, `from-` (open-ended), `-to` (starting at zero) or a single value.
     fn parse(i: &str) -> IResult<&str, Self> {
-        let (i, (from, to)) = separated_pair(digit1, char('-'), digit1)(i)?;
+        let (i, from) = opt(digit1)(i)?;
+        let (i, dash) = opt(char('-'))(i)?;
+        let (i, to) = match dash {
+            Some(_) => opt(digit1)(i)?,
+            None => (i, from),
+        };
+        if from.is_none() && to.is_none() {
+            return Err(nom::Err::Error(Error::new(i, ErrorKind::Digit)));
+        }
         Ok((
             i,
             Self {
-                from: from.parse().unwrap(),
-                to: to.parse().unwrap(),
+                from: from.map_or(0, |f| f.parse().unwrap()),
+                to: to.map_or(usize::MAX, |t| t.parse().unwrap()),
             },
         ))
     }
-
-    fn fits(&self, value: usize) -> bool {
-        self.from <= value && value <= self.to
-    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -82,7 +100,7 @@ struct FieldConstraint {
 
 impl FieldConstraint {
     fn parse(i: &str) -> IResult<&str, Self> {
-        let (i, name) = terminated(is_not(":"), tuple((is_a(":"), multispace0)))(i)?;
+        let (i, name) = terminated(is_not(":\r\n"), tuple((is_a(":"), multispace0)))(i)?;
         let (i, ranges) = separated_list1(tag(" or "), Range::parse)(i)?;
         Ok((
             i,
@@ -92,15 +110,6 @@ impl FieldConstraint {
             },
         ))
     }
-
-    fn fits(&self, value: usize) -> bool {
-        for range in self.ranges.iter() {
-            if range.fits(value) {
-                return true;
-            }
-        }
-        false
-    }
 }
 
 #[derive(Debug, Clone)]
@@ -109,10 +118,6 @@ struct Ticket {
 }
 
 impl Ticket {
-    fn empty() -> Self {
-        Self { fields: Vec::new() }
-    }
-
     fn parse(i: &str) -> IResult<&str, Self> {
         let (i, values) = separated_list1(char(','), digit1)(i)?;
         let fields = values.iter().map(|v| v.parse().unwrap()).collect();
@@ -127,17 +132,18 @@ impl Ticket {
 #[derive(Debug, Clone)]
 struct Notes {
     constraints: Vec<FieldConstraint>,
+    index: IntervalIndex,
     my_ticket: Ticket,
     tickets: Vec<Ticket>,
 }
 
 impl Notes {
     fn read_from(input: &Path) -> Result<Self> {
-        let input = read_to_string(&input)?;
+        let input = read_to_string(input)?;
 
         match Self::parse(&input).finish() {
             Ok((i, notes)) => {
-                if i.len() > 0 {
+                if !i.is_empty() {
                     bail!("Could not parse all of the input, {} bytes left", i.len());
                 }
                 Ok(notes)
@@ -164,6 +170,7 @@ impl Notes {
         Ok((
             i,
             Self {
+                index: IntervalIndex::new(&constraints),
                 constraints,
                 my_ticket,
                 tickets,
@@ -172,85 +179,542 @@ impl Notes {
     }
 
     fn fits(&self, value: usize) -> bool {
-        for constr in self.constraints.iter() {
-            if constr.fits(value) {
-                return true;
-            }
-        }
-        false
+        !self.index.accepting(value).is_empty()
     }
 
-    fn infer_fields(&self) -> Result<Vec<FieldConstraint>> {
-        let mut possible_fields: Vec<HashSet<FieldConstraint>> =
-            std::iter::repeat(self.constraints.clone().into_iter().collect())
-                .take(self.my_ticket.len())
-                .collect();
-
+    /// Which fields each column can hold, given the valid tickets.
+    fn candidates(&self) -> Candidates {
+        let mut ruled_out = vec![vec![None; self.constraints.len()]; self.my_ticket.len()];
         for ticket in self.valid_tickets().iter() {
-            for (idx, value) in ticket.fields.iter().enumerate() {
-                possible_fields[idx] = possible_fields[idx]
-                    .clone()
-                    .into_iter()
-                    .filter(|c| c.fits(*value))
-                    .collect();
+            for (column, value) in ticket.fields.iter().enumerate() {
+                let accepting = self.index.accepting(*value);
+                for (field, ruled_out) in ruled_out[column].iter_mut().enumerate() {
+                    if ruled_out.is_none() && !accepting.contains(field) {
+                        *ruled_out = Some(*value);
+                    }
+                }
             }
         }
+        Candidates { ruled_out }
+    }
 
-        for (idx, left) in possible_fields.iter().enumerate() {
-            eprintln!("Num possible fields left at {}: {}", idx, left.len());
-        }
-
-        let mut done: HashMap<usize, FieldConstraint> = HashMap::new();
+    fn infer_fields(&self) -> Result<Vec<FieldConstraint>> {
+        let candidates = self.candidates();
+        let name = |field: &usize| self.constraints[*field].name.clone();
 
-        while done.len() < self.my_ticket.len() {
-            let (idx, constraint) = possible_fields
+        match candidates.assign(10) {
+            Assignment::Unique(fields) => Ok(fields
                 .iter()
-                .enumerate()
-                .filter(|(_, c)| c.len() == 1)
-                .next()
-                .map(|(idx, v)| (idx, v.iter().next().unwrap().clone()))
-                .unwrap();
-            for pf in possible_fields.iter_mut() {
-                pf.remove(&constraint);
+                .map(|field| self.constraints[*field].clone())
+                .collect()),
+            Assignment::Ambiguous(alternatives) => {
+                let mut msg = format!(
+                    "Field assignment is not unique, found {} alternatives:",
+                    alternatives.len()
+                );
+                for column in 0..self.my_ticket.len() {
+                    let mut options: Vec<_> = alternatives.iter().map(|a| a[column]).collect();
+                    options.sort_unstable();
+                    options.dedup();
+                    if options.len() > 1 {
+                        let options: Vec<_> = options.iter().map(name).collect();
+                        msg.push_str(&format!("\n  column {}: {}", column, options.join(" | ")));
+                    }
+                }
+                bail!(msg)
             }
-            done.insert(idx, constraint);
-        }
-
-        for (idx, left) in possible_fields.iter().enumerate() {
-            if left.len() != 0 {
-                bail!("Still {} choices for field at pos {}", left.len(), idx);
+            Assignment::Impossible { columns, fields } => {
+                let names: Vec<_> = fields.iter().map(name).collect();
+                let mut msg = format!(
+                    "No valid field assignment: columns {:?} only fit the {} fields [{}].",
+                    columns,
+                    fields.len(),
+                    names.join(", ")
+                );
+                for column in columns.iter() {
+                    let excluded: Vec<_> = candidates.ruled_out[*column]
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(field, value)| {
+                            value.map(|v| format!("{} (by {})", name(&field), v))
+                        })
+                        .collect();
+                    msg.push_str(&format!(
+                        "\n  column {} rules out {}",
+                        column,
+                        excluded.join(", ")
+                    ));
+                }
+                bail!(msg)
             }
         }
-
-        Ok((0..self.my_ticket.len())
-            .map(|idx| done.get(&idx).unwrap().clone())
-            .collect())
     }
 
     fn ticket_scanning_error_rate(&self) -> usize {
-        let mut rate = 0;
-        for ticket in self.tickets.iter() {
-            for value in ticket.fields.iter() {
-                if !self.fits(*value) {
-                    rate += value;
+        self.rejections().iter().map(|r| r.value).sum()
+    }
+
+    /// Every value of a nearby ticket that fits no field at all.
+    fn rejections(&self) -> Vec<Rejection> {
+        let mut rv = Vec::new();
+        for (ticket, t) in self.tickets.iter().enumerate() {
+            for (column, value) in t.fields.iter().enumerate() {
+                if self.fits(*value) {
+                    continue;
                 }
+                let (boundary, field) = self
+                    .constraints
+                    .iter()
+                    .flat_map(|c| c.ranges.iter().flat_map(move |r| [(r.from, c), (r.to, c)]))
+                    .min_by_key(|(boundary, _)| boundary.abs_diff(*value))
+                    .map(|(boundary, c)| (boundary, c.name.clone()))
+                    .unwrap_or_default();
+                rv.push(Rejection {
+                    ticket,
+                    column,
+                    value: *value,
+                    boundary,
+                    field,
+                });
             }
         }
-        rate
+        rv
     }
 
     fn valid_tickets(&self) -> Vec<Ticket> {
         self.tickets
             .iter()
-            .filter(|t| -> bool {
-                for value in t.fields.iter() {
-                    if !self.fits(*value) {
-                        return false;
-                    }
-                }
-                true
-            })
+            .filter(|t| t.fields.iter().all(|v| self.fits(*v)))
             .cloned()
             .collect()
     }
+
+    /// All valid nearby tickets with the inferred field names as header.
+    fn to_csv(&self) -> Result<String> {
+        let escape = |cell: &str| {
+            if cell.contains([',', '"', '\n']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.to_string()
+            }
+        };
+        let header: Vec<_> = self
+            .infer_fields()?
+            .iter()
+            .map(|c| escape(&c.name))
+            .collect();
+        let mut rv = header.join(",");
+        rv.push('\n');
+        for ticket in self.valid_tickets() {
+            let row: Vec<_> = ticket.fields.iter().map(|v| v.to_string()).collect();
+            rv.push_str(&row.join(","));
+            rv.push('\n');
+        }
+        Ok(rv)
+    }
+}
+
+/// A nearby ticket value outside of all ranges.
+#[derive(Debug, Clone, PartialEq)]
+struct Rejection {
+    ticket: usize,
+    column: usize,
+    value: usize,
+    // closest range boundary and the field it belongs to
+    boundary: usize,
+    field: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ticket {}, column {}: {} fits no field (nearest boundary: {} of {})",
+            self.ticket, self.column, self.value, self.boundary, self.field
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct FieldSet {
+    words: Vec<u64>,
+}
+
+impl FieldSet {
+    fn insert(&mut self, field: usize) {
+        if self.words.len() <= field / 64 {
+            self.words.resize(field / 64 + 1, 0);
+        }
+        self.words[field / 64] |= 1 << (field % 64);
+    }
+
+    fn remove(&mut self, field: usize) {
+        if let Some(word) = self.words.get_mut(field / 64) {
+            *word &= !(1 << (field % 64));
+        }
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+
+    fn contains(&self, field: usize) -> bool {
+        self.words
+            .get(field / 64)
+            .is_some_and(|word| word & (1 << (field % 64)) != 0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+/// The ranges of all constraints merged into disjoint segments of values accepted by the same
+/// fields.
+#[derive(Debug, Clone)]
+struct IntervalIndex {
+    // first value of each segment, the segment extends up to the start of the next one
+    starts: Vec<usize>,
+    fields: Vec<FieldSet>,
+}
+
+impl IntervalIndex {
+    fn new(constraints: &[FieldConstraint]) -> Self {
+        // (value, field, +1 for a range starting at value, -1 for one ending before it)
+        let mut events: Vec<(usize, usize, i64)> = Vec::new();
+        for (field, constr) in constraints.iter().enumerate() {
+            for range in constr.ranges.iter().filter(|r| r.from <= r.to) {
+                events.push((range.from, field, 1));
+                if range.to < usize::MAX {
+                    events.push((range.to + 1, field, -1));
+                }
+            }
+        }
+        events.sort_unstable();
+
+        let mut starts = vec![0];
+        let mut fields = vec![FieldSet::default()];
+        let mut active = vec![0; constraints.len()];
+        let mut current = FieldSet::default();
+        for (idx, (value, field, delta)) in events.iter().enumerate() {
+            active[*field] += delta;
+            if active[*field] > 0 {
+                current.insert(*field);
+            } else {
+                current.remove(*field);
+            }
+            let last_at_value = events.get(idx + 1).is_none_or(|next| next.0 != *value);
+            if last_at_value && fields.last() != Some(&current) {
+                if starts.last() == Some(value) {
+                    fields.pop();
+                    starts.pop();
+                }
+                starts.push(*value);
+                fields.push(current.clone());
+            }
+        }
+        Self { starts, fields }
+    }
+
+    /// All fields accepting `value`.
+    fn accepting(&self, value: usize) -> &FieldSet {
+        let segment = self.starts.partition_point(|start| *start <= value) - 1;
+        &self.fields[segment]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Assignment {
+    /// Field (constraint index) for every column.
+    Unique(Vec<usize>),
+    /// All assignments found (up to a limit).
+    Ambiguous(Vec<Vec<usize>>),
+    /// These columns fit fewer distinct fields than there are columns.
+    Impossible {
+        columns: Vec<usize>,
+        fields: Vec<usize>,
+    },
+}
+
+/// Bipartite graph between columns and the fields they can hold.
+#[derive(Debug, Clone)]
+struct Candidates {
+    // for every column and field, the first value ruling the field out
+    ruled_out: Vec<Vec<Option<usize>>>,
+}
+
+impl Candidates {
+    fn fits(&self, column: usize, field: usize) -> bool {
+        self.ruled_out[column][field].is_none()
+    }
+
+    fn num_fields(&self) -> usize {
+        self.ruled_out.first().map_or(0, |f| f.len())
+    }
+
+    /// Enumerate assignments of distinct fields to all columns, stopping after `limit` (but
+    /// at least two, as only a second assignment rules out uniqueness).
+    fn assign(&self, limit: usize) -> Assignment {
+        let mut columns: Vec<_> = (0..self.ruled_out.len()).collect();
+        let available = vec![true; self.num_fields()];
+        if let Err(impossible) = self.matching(&columns, &available) {
+            return impossible;
+        }
+
+        // most constrained columns first
+        columns.sort_by_key(|c| (0..self.num_fields()).filter(|f| self.fits(*c, *f)).count());
+        let mut found = Vec::new();
+        let mut current = vec![0; self.ruled_out.len()];
+        self.enumerate(&columns, available, &mut current, &mut found, limit.max(2));
+
+        if found.len() == 1 {
+            Assignment::Unique(found.pop().unwrap())
+        } else {
+            Assignment::Ambiguous(found)
+        }
+    }
+
+    fn enumerate(
+        &self,
+        columns: &[usize],
+        mut available: Vec<bool>,
+        current: &mut Vec<usize>,
+        found: &mut Vec<Vec<usize>>,
+        limit: usize,
+    ) {
+        let (column, rest) = match columns.split_first() {
+            Some(split) => split,
+            None => {
+                found.push(current.clone());
+                return;
+            }
+        };
+        for field in 0..available.len() {
+            if found.len() >= limit {
+                return;
+            }
+            if !available[field] || !self.fits(*column, field) {
+                continue;
+            }
+            available[field] = false;
+            // only descend if the remaining columns can still be matched
+            if self.matching(rest, &available).is_ok() {
+                current[*column] = field;
+                self.enumerate(rest, available.clone(), current, found, limit);
+            }
+            available[field] = true;
+        }
+    }
+
+    /// Match every column to a distinct available field via augmenting paths.
+    ///
+    /// If that is impossible, returns a set of columns together with the strictly fewer fields
+    /// they fit (Hall's theorem), found as the alternating tree of an unmatched column.
+    /// Otherwise the column owning each field.
+    fn matching(
+        &self,
+        columns: &[usize],
+        available: &[bool],
+    ) -> std::result::Result<Vec<Option<usize>>, Assignment> {
+        let mut owner: Vec<Option<usize>> = vec![None; available.len()];
+        for column in columns.iter() {
+            let mut visited = vec![false; available.len()];
+            if !self.augment(*column, available, &mut owner, &mut visited) {
+                let fields: Vec<_> = (0..available.len()).filter(|f| visited[*f]).collect();
+                let mut columns: Vec<_> = fields.iter().filter_map(|f| owner[*f]).collect();
+                columns.push(*column);
+                columns.sort_unstable();
+                return Err(Assignment::Impossible { columns, fields });
+            }
+        }
+        Ok(owner)
+    }
+
+    fn augment(
+        &self,
+        column: usize,
+        available: &[bool],
+        owner: &mut Vec<Option<usize>>,
+        visited: &mut Vec<bool>,
+    ) -> bool {
+        for field in 0..available.len() {
+            if !available[field] || visited[field] || !self.fits(column, field) {
+                continue;
+            }
+            visited[field] = true;
+            let free = match owner[field] {
+                None => true,
+                Some(other) => self.augment(other, available, owner, visited),
+            };
+            if free {
+                owner[field] = Some(column);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(fits: &[&str]) -> Candidates {
+        Candidates {
+            ruled_out: fits
+                .iter()
+                .map(|row| {
+                    row.chars()
+                        .map(|c| if c == '1' { None } else { Some(0) })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    fn example() -> Notes {
+        Notes::parse(
+            "class: 1-3 or 5-7\nrow: 6-11 or 33-44\nseat: 13-40 or 45-50\n\n\
+             your ticket:\n7,1,14\n\n\
+             nearby tickets:\n7,3,47\n40,4,50\n55,2,20\n38,6,12\n",
+        )
+        .unwrap()
+        .1
+    }
+
+    #[test]
+    fn test_index() {
+        let notes = Notes::parse(
+            "a: 1-3 or 5-7\nb: 6-11 or 20\nc: 30- or -2\nd: 3-6 or 4-4\n\n\
+             your ticket:\n1,1,1,1\n\nnearby tickets:\n1,1,1,1\n",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            notes.constraints[2].ranges,
+            vec![
+                Range {
+                    from: 30,
+                    to: usize::MAX
+                },
+                Range { from: 0, to: 2 }
+            ]
+        );
+        assert_eq!(
+            notes.index.starts,
+            vec![0, 1, 3, 4, 5, 6, 7, 8, 12, 20, 21, 30]
+        );
+
+        let accepting = |v| {
+            let fields = notes.index.accepting(v);
+            (0..4).filter(|f| fields.contains(*f)).collect::<Vec<_>>()
+        };
+        for value in 0..40 {
+            let expected: Vec<_> = notes
+                .constraints
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.ranges.iter().any(|r| r.from <= value && value <= r.to))
+                .map(|(field, _)| field)
+                .collect();
+            assert_eq!(accepting(value), expected, "value {}", value);
+        }
+        assert_eq!(accepting(usize::MAX), vec![2]);
+        assert!(notes.index.accepting(15).is_empty());
+        assert!(Range::parse("-").is_err());
+    }
+
+    #[test]
+    fn test_rejections() {
+        let notes = example();
+        let rejections = notes.rejections();
+        assert_eq!(notes.ticket_scanning_error_rate(), 71);
+        assert_eq!(
+            rejections[0],
+            Rejection {
+                ticket: 1,
+                column: 1,
+                value: 4,
+                boundary: 3,
+                field: "class".into()
+            }
+        );
+        assert_eq!(
+            rejections[1].to_string(),
+            "ticket 2, column 0: 55 fits no field (nearest boundary: 50 of seat)"
+        );
+        assert_eq!((rejections[2].boundary, rejections[2].column), (11, 2));
+    }
+
+    #[test]
+    fn test_csv() {
+        let notes = Notes::parse(
+            "class: 0-1 or 4-19\nrow: 0-5 or 8-19\nseat: 0-13 or 16-19\n\n\
+             your ticket:\n11,12,13\n\n\
+             nearby tickets:\n3,9,18\n15,1,5\n5,14,9\n5,14,99\n",
+        )
+        .unwrap()
+        .1;
+        assert_eq!(
+            notes.to_csv().unwrap(),
+            "row,class,seat\n3,9,18\n15,1,5\n5,14,9\n"
+        );
+    }
+
+    #[test]
+    fn test_unique() {
+        // no column has a single candidate, greedy elimination would be stuck
+        let c = candidates(&["110", "011", "001"]);
+        assert_eq!(c.assign(10), Assignment::Unique(vec![0, 1, 2]));
+
+        let notes = Notes::parse(
+            "class: 0-1 or 4-19\nrow: 0-5 or 8-19\nseat: 0-13 or 16-19\n\n\
+             your ticket:\n11,12,13\n\n\
+             nearby tickets:\n3,9,18\n15,1,5\n5,14,9\n",
+        )
+        .unwrap()
+        .1;
+        let names: Vec<_> = notes
+            .infer_fields()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["row", "class", "seat"]);
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let c = candidates(&["110", "110", "111"]);
+        assert_eq!(
+            c.assign(10),
+            Assignment::Ambiguous(vec![vec![0, 1, 2], vec![1, 0, 2]])
+        );
+        assert_eq!(
+            c.assign(1),
+            Assignment::Ambiguous(vec![vec![0, 1, 2], vec![1, 0, 2]])
+        );
+    }
+
+    #[test]
+    fn test_impossible() {
+        let c = candidates(&["011", "100", "100", "111"]);
+        assert_eq!(
+            c.assign(10),
+            Assignment::Impossible {
+                columns: vec![1, 2],
+                fields: vec![0]
+            }
+        );
+
+        let notes = Notes::parse(
+            "a: 1-5\nb: 1-5 or 7-7\n\nyour ticket:\n1,1\n\nnearby tickets:\n7,7\n2,3\n",
+        )
+        .unwrap()
+        .1;
+        let err = notes.infer_fields().unwrap_err().to_string();
+        assert!(
+            err.starts_with("No valid field assignment: columns [0, 1] only fit the 1 fields [b].")
+        );
+        assert!(err.contains("column 0 rules out a (by 7)"));
+    }
 }
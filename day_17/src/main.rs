@@ -1,36 +1,121 @@
 use anyhow::{bail, Context, Result};
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, is_not, tag, take_while1},
-    character::complete::{char, digit1, line_ending, multispace0, none_of, one_of},
+    character::complete::{char, line_ending},
     combinator::value,
     multi::{many0, many1, separated_list1},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
     Finish, IResult,
 };
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
-    let cubes = ConwayCubes3D::read_from(&input)?;
-    eprintln!("Cubes: {:#?}", cubes);
-    part1(&cubes);
+    match args.get(1) {
+        None => {
+            let cubes = ConwayCubes3D::read_from(&input)?;
+            part1(&cubes);
 
-    let cubes = ConwayCubes4D::read_from(&input)?;
-    part2(&cubes);
+            let cubes = ConwayCubes4D::read_from(&input)?;
+            part2(&cubes);
+        }
+        Some(dimensions) => {
+            let options = Options::from_args(&args[2..])?;
+            let num_active = match dimensions.parse()? {
+                2 => simulate::<2>(&input, &options)?,
+                3 => simulate::<3>(&input, &options)?,
+                4 => simulate::<4>(&input, &options)?,
+                5 => simulate::<5>(&input, &options)?,
+                6 => simulate::<6>(&input, &options)?,
+                7 => simulate::<7>(&input, &options)?,
+                8 => simulate::<8>(&input, &options)?,
+                n => bail!("Unsupported number of dimensions: {}", n),
+            };
+            println!(
+                "{}D after {} iterations with {}: {} active cubes",
+                dimensions, options.cycles, options.rule, num_active
+            );
+        }
+    }
 
     Ok(())
 }
 
+struct Options {
+    cycles: usize,
+    rule: Rule,
+    symmetric: bool,
+    // fixed coordinates beyond x and y of each slice to print
+    slices: Vec<Vec<i64>>,
+}
+
+impl Options {
+    /// Usage: `day_17 <input> <dimensions> [--cycles <n>] [--rule <B3/S23>] [--symmetric]
+    /// [--slice <z>,<w>,...]...`
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut options = Self {
+            cycles: 6,
+            rule: Rule::default(),
+            symmetric: false,
+            slices: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--symmetric" {
+                options.symmetric = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .with_context(|| format!("{} requires a value", arg))?;
+            match arg.as_str() {
+                "--cycles" => {
+                    options.cycles = value
+                        .parse()
+                        .with_context(|| format!("Invalid number of cycles: {}", value))?
+                }
+                "--rule" => options.rule = Rule::parse(value)?,
+                "--slice" => options.slices.push(
+                    value
+                        .split(',')
+                        .map(|c| {
+                            c.parse()
+                                .with_context(|| format!("Invalid slice: {}", value))
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                _ => bail!("Unknown argument: {}", arg),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn simulate<const N: usize>(input: &Path, options: &Options) -> Result<usize> {
+    let mut cubes = ConwayCubes::<N>::read_from(input)?;
+    cubes.rule = options.rule.clone();
+    if options.symmetric {
+        let cubes = SymmetricCubes::reduce(&cubes)
+            .with_context(|| "Initial state is not symmetric.")?
+            .run_updates(options.cycles);
+        for slice in options.slices.iter() {
+            println!("{}", cubes.render_slice(slice)?);
+        }
+        Ok(cubes.num_active())
+    } else {
+        let cubes = cubes.run_updates(options.cycles);
+        for slice in options.slices.iter() {
+            println!("{}", cubes.render_slice(slice)?);
+        }
+        Ok(cubes.num_active())
+    }
+}
+
 fn part1(cubes: &ConwayCubes3D) {
     let updated = cubes.run_updates(6);
 
@@ -44,7 +129,7 @@ fn part2(cubes: &ConwayCubes4D) {
     let updated = cubes.run_updates(6);
 
     println!(
-        "(part1) After 6 iterations: {} active cubes",
+        "(part2) After 6 iterations: {} active cubes",
         updated.num_active()
     );
 }
@@ -64,19 +149,152 @@ impl CubeState {
     }
 }
 
-type Position3D = (i64, i64, i64);
+/// Life-like rule: number of active neighbours for an inactive cube to become active (birth)
+/// and for an active one to stay so (survival).
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    birth: BTreeSet<usize>,
+    survival: BTreeSet<usize>,
+}
+
+impl Default for Rule {
+    /// The puzzle's rule, B3/S23.
+    fn default() -> Self {
+        Self {
+            birth: BTreeSet::from([3]),
+            survival: BTreeSet::from([2, 3]),
+        }
+    }
+}
+
+impl Rule {
+    /// Parse `B<counts>/S<counts>`, counts being single digits (`B36/S23`) or, to allow counts
+    /// above nine, comma separated (`B3,12/S2,3`).
+    pub fn parse(spec: &str) -> Result<Self> {
+        let counts = |part: &str, prefix: char| -> Result<BTreeSet<usize>> {
+            let counts = part
+                .strip_prefix(prefix)
+                .with_context(|| format!("Expected {} in rule: {}", prefix, spec))?;
+            let invalid = || format!("Invalid counts in rule: {}", spec);
+            if counts.contains(',') {
+                counts
+                    .split(',')
+                    .map(|c| c.parse().with_context(invalid))
+                    .collect()
+            } else {
+                counts
+                    .chars()
+                    .map(|c| c.to_digit(10).map(|d| d as usize).with_context(invalid))
+                    .collect()
+            }
+        };
+        let (birth, survival) = spec
+            .split_once('/')
+            .with_context(|| format!("Expected B../S.. rule, got: {}", spec))?;
+        let rule = Self {
+            birth: counts(birth, 'B')?,
+            survival: counts(survival, 'S')?,
+        };
+        if rule.birth.contains(&0) {
+            bail!("Rules with B0 are not supported, they activate infinitely many cubes.");
+        }
+        Ok(rule)
+    }
+
+    fn next(&self, active: bool, neighbours: usize) -> bool {
+        if active {
+            self.survival.contains(&neighbours)
+        } else {
+            self.birth.contains(&neighbours)
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = |counts: &BTreeSet<usize>| {
+            let counts: Vec<_> = counts.iter().map(|c| c.to_string()).collect();
+            if counts.iter().all(|c| c.len() == 1) {
+                counts.join("")
+            } else {
+                counts.join(",")
+            }
+        };
+        write!(f, "B{}/S{}", counts(&self.birth), counts(&self.survival))
+    }
+}
+
+/// Coordinates `x, y, z, w, ...`
+type Position<const N: usize> = [i64; N];
+
+/// Print the `x`/`y` slice at the given higher coordinates like the puzzle does, covering the
+/// extent of all active cubes.
+///
+/// `canonical` maps positions to how they are stored in `active`.
+fn render_slice<const N: usize>(
+    active: &HashSet<Position<N>>,
+    canonical: fn(&Position<N>) -> Position<N>,
+    higher: &[i64],
+) -> Result<String> {
+    if higher.len() + 2 != N {
+        bail!("Slice needs {} coordinates, got {}.", N - 2, higher.len());
+    }
+    let axes = ["z", "w", "v", "u", "t", "s"];
+    let header: Vec<_> = higher
+        .iter()
+        .zip(axes.iter())
+        .map(|(coord, axis)| format!("{}={}", axis, coord))
+        .collect();
+    let mut rv = header.join(", ");
+
+    let xs = active.iter().map(|pos| pos[0]);
+    let ys = active.iter().map(|pos| pos[1]);
+    let (min_x, max_x) = (xs.clone().min().unwrap_or(0), xs.max().unwrap_or(-1));
+    let (min_y, max_y) = (ys.clone().min().unwrap_or(0), ys.max().unwrap_or(-1));
+    for y in min_y..=max_y {
+        rv.push('\n');
+        for x in min_x..=max_x {
+            let mut pos = [0; N];
+            pos[0] = x;
+            pos[1] = y;
+            pos[2..].copy_from_slice(higher);
+            rv.push(if active.contains(&canonical(&pos)) {
+                '#'
+            } else {
+                '.'
+            });
+        }
+    }
+    rv.push('\n');
+    Ok(rv)
+}
+
+fn shifted<const N: usize>(pos: &Position<N>, offset: &Position<N>) -> Position<N> {
+    let mut shifted = *pos;
+    for (coord, delta) in shifted.iter_mut().zip(offset.iter()) {
+        *coord += delta;
+    }
+    shifted
+}
+
+type ConwayCubes3D = ConwayCubes<3>;
+type ConwayCubes4D = ConwayCubes<4>;
 
 #[derive(Debug, Clone)]
-struct ConwayCubes3D {
-    active: HashSet<Position3D>,
+struct ConwayCubes<const N: usize> {
+    active: HashSet<Position<N>>,
+    rule: Rule,
 }
 
-impl ConwayCubes3D {
+impl<const N: usize> ConwayCubes<N> {
     fn read_from(input: &Path) -> Result<Self> {
+        if N < 2 {
+            bail!("Need at least two dimensions, got {}.", N);
+        }
         let input = read_to_string(input)?;
         match Self::parse(&input).finish() {
             Ok((i, cubes)) => {
-                if i.len() == 0 {
+                if i.is_empty() {
                     Ok(cubes)
                 } else {
                     bail!("Did not consume all of input, {} bytes left!", i.len());
@@ -88,6 +306,7 @@ impl ConwayCubes3D {
         }
     }
 
+    /// Parse the initial 2D slice, all higher coordinates are zero.
     fn parse(i: &str) -> IResult<&str, Self> {
         let (i, lines) = separated_list1(line_ending, many1(CubeState::parse))(i)?;
 
@@ -96,47 +315,68 @@ impl ConwayCubes3D {
         for (idx_y, line) in lines.iter().enumerate() {
             for (idx_x, state) in line.iter().enumerate() {
                 if let CubeState::Active = state {
-                    active.insert((idx_x as i64, idx_y as i64, 0));
+                    let mut pos = [0; N];
+                    pos[0] = idx_x as i64;
+                    pos[1] = idx_y as i64;
+                    active.insert(pos);
                 }
             }
         }
         let (i, _) = many0(line_ending)(i)?;
-        Ok((i, Self { active }))
+        Ok((
+            i,
+            Self {
+                active,
+                rule: Rule::default(),
+            },
+        ))
+    }
+
+    /// All `3^N - 1` offsets to neighbouring cells.
+    fn neighbour_offsets() -> Vec<Position<N>> {
+        let mut offsets = vec![[0; N]];
+        for axis in 0..N {
+            offsets = offsets
+                .into_iter()
+                .flat_map(|offset| {
+                    (-1..2).map(move |delta| {
+                        let mut offset = offset;
+                        offset[axis] = delta;
+                        offset
+                    })
+                })
+                .collect();
+        }
+        offsets.retain(|offset| offset.iter().any(|d| *d != 0));
+        offsets
     }
 
     fn update(&self) -> Self {
-        let mut num_neighbors: HashMap<Position3D, usize> = HashMap::new();
-
-        for (x, y, z) in self.active.iter() {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    for dz in -1..2 {
-                        if dx == 0 && dy == 0 && dz == 0 {
-                            continue;
-                        }
-                        *num_neighbors.entry((x + dx, y + dy, z + dz)).or_insert(0) += 1;
-                    }
-                }
+        let offsets = Self::neighbour_offsets();
+        let mut num_neighbors: HashMap<Position<N>, usize> = HashMap::new();
+
+        for pos in self.active.iter() {
+            // isolated cubes might survive as well
+            num_neighbors.entry(*pos).or_insert(0);
+            for offset in offsets.iter() {
+                *num_neighbors.entry(shifted(pos, offset)).or_insert(0) += 1;
             }
         }
-        let mut updated = HashSet::new();
 
-        let of_interest: HashMap<Position3D, usize> = num_neighbors
+        let updated = num_neighbors
             .into_iter()
-            .filter_map(|(k, v)| if v == 2 || v == 3 { Some((k, v)) } else { None })
+            .filter(|(pos, count)| self.rule.next(self.active.contains(pos), *count))
+            .map(|(pos, _)| pos)
             .collect();
 
-        for (pos, count) in of_interest.into_iter() {
-            if count == 3 {
-                // is always active
-                updated.insert(pos);
-            } else if self.active.contains(&pos) {
-                // only remains active
-                updated.insert(pos);
-            }
+        Self {
+            active: updated,
+            rule: self.rule.clone(),
         }
+    }
 
-        Self { active: updated }
+    fn render_slice(&self, higher: &[i64]) -> Result<String> {
+        render_slice(&self.active, |pos| *pos, higher)
     }
 
     fn run_updates(&self, count: usize) -> Self {
@@ -152,83 +392,82 @@ impl ConwayCubes3D {
     }
 }
 
-type Position4D = (i64, i64, i64, i64);
-
+/// Cubes symmetric under mirroring and permuting all axes beyond `x` and `y`, as is the case
+/// when starting from a 2D slice.
+///
+/// Only the canonical representative of each orbit (non-negative, sorted higher coordinates)
+/// is stored.
 #[derive(Debug, Clone)]
-struct ConwayCubes4D {
-    active: HashSet<Position4D>,
+struct SymmetricCubes<const N: usize> {
+    active: HashSet<Position<N>>,
+    rule: Rule,
 }
 
-impl ConwayCubes4D {
-    fn read_from(input: &Path) -> Result<Self> {
-        let input = read_to_string(input)?;
-        match Self::parse(&input).finish() {
-            Ok((i, cubes)) => {
-                if i.len() == 0 {
-                    Ok(cubes)
-                } else {
-                    bail!("Did not consume all of input, {} bytes left!", i.len());
-                }
-            }
-            Err(e) => {
-                bail!("Error during parsing: {}", e);
-            }
+impl<const N: usize> SymmetricCubes<N> {
+    /// `None` if `cubes` is not symmetric.
+    fn reduce(cubes: &ConwayCubes<N>) -> Option<Self> {
+        let active: HashSet<_> = cubes.active.iter().map(Self::canonical).collect();
+        let reduced = Self {
+            active,
+            rule: cubes.rule.clone(),
+        };
+        if reduced.num_active() == cubes.num_active() {
+            Some(reduced)
+        } else {
+            None
         }
     }
 
-    fn parse(i: &str) -> IResult<&str, Self> {
-        let (i, lines) = separated_list1(line_ending, many1(CubeState::parse))(i)?;
-
-        let mut active = HashSet::new();
+    fn canonical(pos: &Position<N>) -> Position<N> {
+        let mut canonical = *pos;
+        for coord in canonical[2..].iter_mut() {
+            *coord = coord.abs();
+        }
+        canonical[2..].sort_unstable();
+        canonical
+    }
 
-        for (idx_y, line) in lines.iter().enumerate() {
-            for (idx_x, state) in line.iter().enumerate() {
-                if let CubeState::Active = state {
-                    active.insert((idx_x as i64, idx_y as i64, 0, 0));
-                }
-            }
+    /// Size of the orbit of a canonical position.
+    fn weight(pos: &Position<N>) -> usize {
+        let higher = &pos[2..];
+        let mirrored = 1 << higher.iter().filter(|c| **c != 0).count();
+        let mut permutations: usize = (1..=higher.len()).product();
+        for run in higher.chunk_by(|a, b| a == b) {
+            permutations /= (1..=run.len()).product::<usize>();
         }
-        let (i, _) = many0(line_ending)(i)?;
-        Ok((i, Self { active }))
+        mirrored * permutations
     }
 
     fn update(&self) -> Self {
-        let mut num_neighbors: HashMap<Position4D, usize> = HashMap::new();
-
-        for (x, y, z, w) in self.active.iter() {
-            for dx in -1..2 {
-                for dy in -1..2 {
-                    for dz in -1..2 {
-                        for dw in -1..2 {
-                            if dx == 0 && dy == 0 && dz == 0 && dw == 0 {
-                                continue;
-                            }
-                            *num_neighbors
-                                .entry((x + dx, y + dy, z + dz, w + dw))
-                                .or_insert(0) += 1;
-                        }
-                    }
-                }
+        let offsets = ConwayCubes::<N>::neighbour_offsets();
+        let mut num_neighbors: HashMap<Position<N>, usize> = HashMap::new();
+
+        // Every orbit member of `pos` has the same neighbours up to symmetry, so counting the
+        // neighbours of the representative once per member counts each neighbouring orbit
+        // `weight` times too often.
+        for pos in self.active.iter() {
+            let weight = Self::weight(pos);
+            num_neighbors.entry(*pos).or_insert(0);
+            for offset in offsets.iter() {
+                let neighbor = Self::canonical(&shifted(pos, offset));
+                *num_neighbors.entry(neighbor).or_insert(0) += weight;
             }
         }
-        let mut updated = HashSet::new();
 
-        let of_interest: HashMap<Position4D, usize> = num_neighbors
+        let active = num_neighbors
             .into_iter()
-            .filter_map(|(k, v)| if v == 2 || v == 3 { Some((k, v)) } else { None })
+            .map(|(pos, count)| (pos, count / Self::weight(&pos)))
+            .filter(|(pos, count)| self.rule.next(self.active.contains(pos), *count))
+            .map(|(pos, _)| pos)
             .collect();
-
-        for (pos, count) in of_interest.into_iter() {
-            if count == 3 {
-                // is always active
-                updated.insert(pos);
-            } else if self.active.contains(&pos) {
-                // only remains active
-                updated.insert(pos);
-            }
+        Self {
+            active,
+            rule: self.rule.clone(),
         }
+    }
 
-        Self { active: updated }
+    fn render_slice(&self, higher: &[i64]) -> Result<String> {
+        render_slice(&self.active, Self::canonical, higher)
     }
 
     fn run_updates(&self, count: usize) -> Self {
@@ -240,6 +479,102 @@ impl ConwayCubes4D {
     }
 
     fn num_active(&self) -> usize {
-        self.active.len()
+        self.active.iter().map(Self::weight).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = ".#.\n..#\n###\n";
+
+    #[test]
+    fn test_offsets() {
+        assert_eq!(ConwayCubes::<2>::neighbour_offsets().len(), 8);
+        assert_eq!(ConwayCubes::<3>::neighbour_offsets().len(), 26);
+        assert_eq!(ConwayCubes::<5>::neighbour_offsets().len(), 242);
+    }
+
+    #[test]
+    fn test_example() {
+        let cubes = ConwayCubes3D::parse(EXAMPLE).unwrap().1;
+        assert_eq!(cubes.num_active(), 5);
+        assert_eq!(cubes.run_updates(1).num_active(), 11);
+        assert_eq!(cubes.run_updates(6).num_active(), 112);
+
+        let cubes = ConwayCubes4D::parse(EXAMPLE).unwrap().1;
+        assert_eq!(cubes.run_updates(6).num_active(), 848);
+    }
+
+    #[test]
+    fn test_rule() {
+        assert_eq!(Rule::parse("B3/S23").unwrap(), Rule::default());
+        let rule = Rule::parse("B3,12/S").unwrap();
+        assert!(rule.next(false, 12) && !rule.next(true, 3));
+        assert_eq!(rule.to_string(), "B3,12/S");
+        assert_eq!(Rule::parse("B36/S125").unwrap().to_string(), "B36/S125");
+        assert!(Rule::parse("B0/S23").is_err());
+        assert!(Rule::parse("S23/B3").is_err());
+
+        // isolated cubes survive with S0
+        let mut cubes = ConwayCubes::<2>::parse("#..\n...\n..#\n").unwrap().1;
+        cubes.rule = Rule::parse("B9/S0").unwrap();
+        assert_eq!(cubes.run_updates(3).active, cubes.active);
+    }
+
+    #[test]
+    fn test_render() {
+        let cubes = ConwayCubes3D::parse(EXAMPLE).unwrap().1.run_updates(1);
+        assert_eq!(cubes.render_slice(&[-1]).unwrap(), "z=-1\n#..\n..#\n.#.\n");
+        assert_eq!(cubes.render_slice(&[0]).unwrap(), "z=0\n#.#\n.##\n.#.\n");
+        assert!(cubes.render_slice(&[0, 0]).is_err());
+
+        let cubes = ConwayCubes4D::parse(EXAMPLE).unwrap().1.run_updates(2);
+        let reduced = SymmetricCubes::reduce(&cubes).unwrap();
+        assert_eq!(
+            cubes.render_slice(&[-2, -2]).unwrap(),
+            "z=-2, w=-2\n.....\n.....\n..#..\n.....\n.....\n"
+        );
+        assert_eq!(
+            cubes.render_slice(&[0, -2]).unwrap(),
+            "z=0, w=-2\n###..\n##.##\n#...#\n.#..#\n.###.\n"
+        );
+        for slice in [[0, 0], [0, 1], [1, -2], [-2, 2]] {
+            assert_eq!(
+                reduced.render_slice(&slice).unwrap(),
+                cubes.render_slice(&slice).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_symmetric() {
+        assert_eq!(SymmetricCubes::<6>::weight(&[5, 5, 0, 0, 0, 0]), 1);
+        // 2^3 signs * 4!/2! orders
+        assert_eq!(SymmetricCubes::<6>::weight(&[5, 5, 0, 1, 1, 2]), 96);
+
+        let cubes = ConwayCubes::<5>::parse(EXAMPLE).unwrap().1;
+        let reduced = SymmetricCubes::reduce(&cubes).unwrap();
+        for cycles in 1..4 {
+            let full = cubes.run_updates(cycles);
+            let reduced = reduced.run_updates(cycles);
+            assert_eq!(reduced.num_active(), full.num_active());
+            assert_eq!(
+                SymmetricCubes::reduce(&full).unwrap().active,
+                reduced.active
+            );
+        }
+        assert_eq!(
+            SymmetricCubes::reduce(&ConwayCubes4D::parse(EXAMPLE).unwrap().1)
+                .unwrap()
+                .run_updates(6)
+                .num_active(),
+            848
+        );
+
+        let mut asymmetric = cubes;
+        asymmetric.active.insert([0, 0, 1, 0, 0]);
+        assert!(SymmetricCubes::reduce(&asymmetric).is_none());
     }
 }
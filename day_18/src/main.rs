@@ -1,87 +1,412 @@
 use anyhow::{bail, Context, Result};
 use nom::{
     branch::alt,
-    character::complete::{char, digit0, line_ending, space0},
-    combinator::value,
-    multi::separated_list1,
-    sequence::{delimited, terminated, tuple},
+    character::complete::{char, digit1, space0},
+    combinator::{map_res, value},
+    sequence::{delimited, tuple},
     Finish, IResult,
 };
+use std::convert::TryFrom;
 use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("repl") {
+        return Repl::new(&args[1..])?.run();
+    }
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
     let exprs = Expression::read_from(&input)?;
-    part2(&exprs[..]);
+    match args.get(1) {
+        None => {
+            part1(&exprs[..])?;
+            part2(&exprs[..])?;
+        }
+        Some(spec) => {
+            let table = OperatorTable::parse(spec)?;
+            println!("Result ({}): {}", table, sum(&exprs[..], &table)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn sum(exprs: &[Expression], table: &OperatorTable) -> Result<i64> {
+    let mut sum: i64 = 0;
+    for (idx, expr) in exprs.iter().enumerate() {
+        let value = expr
+            .eval(table)
+            .with_context(|| format!("Cannot evaluate line {}", idx + 1))?;
+        sum = sum
+            .checked_add(value)
+            .with_context(|| format!("Sum overflows at line {}", idx + 1))?;
+    }
+    Ok(sum)
+}
+
+/// Interactive evaluation of single lines under several operator tables.
+struct Repl {
+    tables: Vec<OperatorTable>,
+}
+
+impl Repl {
+    /// Uses part1, part2 and standard precedence if no table specs are given.
+    fn new(specs: &[String]) -> Result<Self> {
+        let tables = if specs.is_empty() {
+            vec![
+                OperatorTable::part1(),
+                OperatorTable::part2(),
+                OperatorTable::standard(),
+            ]
+        } else {
+            specs
+                .iter()
+                .map(|spec| OperatorTable::parse(spec))
+                .collect::<Result<_>>()?
+        };
+        Ok(Self { tables })
+    }
+
+    fn run(&mut self) -> Result<()> {
+        println!("{}", self.handle(":help")?);
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+            if line.trim() == ":quit" {
+                break;
+            }
+            match self.handle(&line) {
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("Error: {:#}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a single line of input, either a `:command` or an expression.
+    fn handle(&mut self, line: &str) -> Result<String> {
+        let line = line.trim();
+        if let Some(command) = line.strip_prefix(':') {
+            let (command, rest) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            let specs = || -> Result<Vec<OperatorTable>> {
+                if rest.trim().is_empty() {
+                    bail!("Usage: :{} <table>[; <table>...]", command);
+                }
+                rest.split(';').map(OperatorTable::parse).collect()
+            };
+            match command {
+                "set" => self.tables = specs()?,
+                "add" => self.tables.extend(specs()?),
+                "tables" => {}
+                "help" => return Ok([
+                    "Enter an expression to evaluate it under each operator table.",
+                    ":set <table>[; <table>...]  replace the operator tables",
+                    ":add <table>[; <table>...]  add operator tables",
+                    ":tables                     list operator tables",
+                    ":quit                       exit",
+                    "Tables are part1, part2, standard or groups like \"^:right > * / % > + -\".",
+                ]
+                .join("\n")),
+                _ => bail!("Unknown command: {}", command),
+            }
+            Ok(self
+                .tables
+                .iter()
+                .enumerate()
+                .map(|(idx, table)| format!("[{}] {}", idx + 1, table))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        } else if line.is_empty() {
+            Ok(String::new())
+        } else {
+            let expr = Expression::parse_full(line)?;
+            let width = self
+                .tables
+                .iter()
+                .map(|t| t.to_string().len())
+                .max()
+                .unwrap_or(0);
+            let mut output = Vec::new();
+            for table in self.tables.iter() {
+                let evaluated = match expr.tree(table) {
+                    Ok(tree) => match tree.eval() {
+                        Ok(value) => format!("{} = {}", tree, value),
+                        Err(e) => format!("{} = {} ({})", tree, e, &line[e.span.clone()]),
+                    },
+                    Err(e) => format!("{}", e),
+                };
+                output.push(format!(
+                    "{:width$} | {}",
+                    table.to_string(),
+                    evaluated,
+                    width = width
+                ));
+            }
+            Ok(output.join("\n"))
+        }
+    }
+}
+
+fn part1(exprs: &[Expression]) -> Result<()> {
+    println!("(part1) Result: {}", sum(exprs, &OperatorTable::part1())?);
+    Ok(())
+}
 
+fn part2(exprs: &[Expression]) -> Result<()> {
+    println!("(part2) Result: {}", sum(exprs, &OperatorTable::part2())?);
     Ok(())
 }
 
-fn part2(exprs: &[Expression]) {
-    let result: i64 = exprs.iter().map(|e| e.eval()).sum();
-    println!("(part2) Result: {}", result);
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// Binding of each operator: higher precedence binds tighter.
+///
+/// Unary minus is part of the operand and binds tighter than any operator, so `-2 ^ 2` is
+/// `(-2) ^ 2 = 4` (unlike the usual `-(2 ^ 2)`).
+#[derive(Debug, Clone, PartialEq)]
+struct OperatorTable {
+    entries: Vec<(Operator, usize, Associativity)>,
+}
+
+impl OperatorTable {
+    /// Strictly left to right.
+    fn part1() -> Self {
+        Self::parse("+ - * / % ^").unwrap()
+    }
+
+    /// Addition and subtraction before everything else.
+    fn part2() -> Self {
+        Self::parse("+ - > * / % ^").unwrap()
+    }
+
+    /// Usual arithmetic, exponentiation associates to the right.
+    fn standard() -> Self {
+        Self::parse("^:right > * / % > + -").unwrap()
+    }
+
+    /// Groups of operators from highest to lowest precedence separated by `>`, an operator
+    /// followed by `:right` associates to the right, e.g. `+ > *:right`. The predefined tables
+    /// are available as `part1`, `part2` and `standard`.
+    fn parse(spec: &str) -> Result<Self> {
+        match spec.trim() {
+            "part1" => return Ok(Self::part1()),
+            "part2" => return Ok(Self::part2()),
+            "standard" => return Ok(Self::standard()),
+            _ => {}
+        }
+        let groups: Vec<_> = spec.split('>').collect();
+        let mut entries = Vec::new();
+        for (idx, group) in groups.iter().enumerate() {
+            let precedence = groups.len() - idx;
+            for token in group.split_whitespace() {
+                let (symbol, assoc) = match token.split_once(':') {
+                    None => (token, Associativity::Left),
+                    Some((symbol, "left")) => (symbol, Associativity::Left),
+                    Some((symbol, "right")) => (symbol, Associativity::Right),
+                    Some((_, assoc)) => bail!("Invalid associativity: {}", assoc),
+                };
+                let op = match Operator::parse(symbol).finish() {
+                    Ok(("", op)) => op,
+                    _ => bail!("Invalid operator: {}", symbol),
+                };
+                if entries.iter().any(|(other, _, _)| *other == op) {
+                    bail!("Operator {} specified twice.", op);
+                }
+                entries.push((op, precedence, assoc));
+            }
+        }
+        if entries.is_empty() {
+            bail!("Operator table without operators: {:?}", spec);
+        }
+        Ok(Self { entries })
+    }
+
+    fn lookup(&self, op: &Operator) -> Result<(usize, Associativity)> {
+        self.entries
+            .iter()
+            .find(|(other, _, _)| other == op)
+            .map(|(_, precedence, assoc)| (*precedence, *assoc))
+            .with_context(|| format!("No precedence defined for {}", op))
+    }
+}
+
+impl fmt::Display for OperatorTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut levels: Vec<_> = self.entries.iter().map(|e| e.1).collect();
+        levels.sort_unstable_by(|a, b| b.cmp(a));
+        levels.dedup();
+        let groups: Vec<_> = levels
+            .iter()
+            .map(|level| {
+                let ops: Vec<_> = self
+                    .entries
+                    .iter()
+                    .filter(|e| e.1 == *level)
+                    .map(|(op, _, assoc)| match assoc {
+                        Associativity::Left => op.to_string(),
+                        Associativity::Right => format!("{}:right", op),
+                    })
+                    .collect();
+                ops.join(" ")
+            })
+            .collect();
+        write!(f, "{}", groups.join(" > "))
+    }
+}
+
+/// Byte offsets into the parsed line.
+type Span = Range<usize>;
+
+/// Expression tree with the operator bindings resolved.
+#[derive(Debug, Clone, PartialEq)]
+enum Ast {
+    Value(i64, Span),
+    Neg(Box<Ast>, Span),
+    Binary(Operator, Box<Ast>, Box<Ast>, Span),
+}
+
+impl Ast {
+    fn span(&self) -> Span {
+        match self {
+            Ast::Value(_, span) | Ast::Neg(_, span) | Ast::Binary(_, _, _, span) => span.clone(),
+        }
+    }
+
+    fn with_span(self, span: Span) -> Self {
+        match self {
+            Ast::Value(value, _) => Ast::Value(value, span),
+            Ast::Neg(inner, _) => Ast::Neg(inner, span),
+            Ast::Binary(op, left, right, _) => Ast::Binary(op, left, right, span),
+        }
+    }
+
+    fn eval(&self) -> Result<i64, EvalError> {
+        match self {
+            Ast::Value(value, _) => Ok(*value),
+            Ast::Neg(inner, span) => inner.eval()?.checked_neg().ok_or(EvalError {
+                reason: "integer overflow",
+                span: span.clone(),
+            }),
+            Ast::Binary(op, left, right, span) => {
+                op.apply(left.eval()?, right.eval()?)
+                    .map_err(|reason| EvalError {
+                        reason,
+                        span: span.clone(),
+                    })
+            }
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, top: bool) -> fmt::Result {
+        match self {
+            Ast::Value(value, _) => write!(f, "{}", value),
+            Ast::Neg(inner, _) => {
+                if !top {
+                    write!(f, "(")?;
+                }
+                write!(f, "-")?;
+                inner.write(f, false)?;
+                if !top {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Ast::Binary(op, left, right, _) => {
+                if !top {
+                    write!(f, "(")?;
+                }
+                left.write(f, false)?;
+                write!(f, " {} ", op)?;
+                right.write(f, false)?;
+                if !top {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fully parenthesised, apart from the outermost operation.
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, true)
+    }
 }
 
+/// Evaluating the sub-expression at `span` failed.
+#[derive(Debug, Clone, PartialEq)]
+struct EvalError {
+    reason: &'static str,
+    span: Span,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} in sub-expression at {}..{}",
+            self.reason, self.span.start, self.span.end
+        )
+    }
+}
+
+impl std::error::Error for EvalError {}
+
 #[derive(Debug, Clone)]
 struct Expression {
     initial: Operand,
     ops: Vec<(Operator, Operand)>,
+    // input length when parsing started, i.e. the total length for a full expression
+    len: usize,
 }
 
 impl Expression {
     fn read_from(input: &Path) -> Result<Vec<Self>> {
-        let input = read_to_string(&input)?;
-        let retval = match terminated(Self::parse_vec, line_ending)(&input) {
-            Ok((rem, exprs)) => {
-                if rem.len() > 0 {
-                    bail!(
-                        "Failed afer parsing {} entries.\nParsed: {:?}\nRemainder: {}",
-                        exprs.len(),
-                        exprs,
-                        rem
-                    );
-                }
-                Ok(exprs)
-            }
-            Err(e) => bail!("Error during parsing: {}", e),
-        };
-        retval
-    }
-
-    fn parse_vec(i: &str) -> IResult<&str, Vec<Expression>> {
-        separated_list1(line_ending, Self::parse)(i)
+        read_to_string(input)?
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| {
+                Self::parse_full(line).with_context(|| format!("Invalid line {}", idx + 1))
+            })
+            .collect()
     }
 
     fn parse(i: &str) -> IResult<&str, Expression> {
+        let len = i.len();
         let (i, initial) = Operand::parse(i)?;
         let (mut i, _) = space0(i)?;
         let mut ops = Vec::new();
-        loop {
-            match tuple((Operator::parse, space0, Operand::parse, space0))(i) {
-                Ok((i_, (op, _, opnd, _))) => {
-                    i = i_;
-                    ops.push((op, opnd));
-                }
-                Err(_) => {
-                    break;
-                }
-            }
+        while let Ok((i_, (op, _, opnd, _))) =
+            tuple((Operator::parse, space0, Operand::parse, space0))(i)
+        {
+            i = i_;
+            ops.push((op, opnd));
         }
-        Ok((i, Self { initial, ops }))
+        Ok((i, Self { initial, ops, len }))
     }
 
     fn parse_full(i: &str) -> Result<Expression> {
         match Self::parse(i).finish() {
             Ok((i, expr)) => {
-                if i.len() > 0 {
+                if !i.is_empty() {
                     bail!("Failed to parse full expression, leftover: {}", i);
                 }
                 Ok(expr)
@@ -90,99 +415,169 @@ impl Expression {
         }
     }
 
-    fn eval(&self) -> i64 {
-        use Operator::*;
-        let mut current = self.initial.clone();
+    /// Resolve the operator sequence into a tree via precedence climbing.
+    fn tree(&self, table: &OperatorTable) -> Result<Ast> {
+        self.tree_within(table, self.len)
+    }
 
-        let mut evaluate_later: Vec<(Operand, Operator)> = Vec::new();
+    /// Tree of a (possibly nested) expression in an input of length `total`.
+    fn tree_within(&self, table: &OperatorTable, total: usize) -> Result<Ast> {
+        let mut ops = self.ops.iter().peekable();
+        let initial = self.initial.tree(table, total)?;
+        Self::climb(initial, &mut ops, 0, table, total)
+    }
 
-        // first pass - apply addition first
-        for (op, operand) in self.ops.iter() {
-            match op {
-                Mult => {
-                    evaluate_later.push((current, Mult));
-                    current = operand.clone();
-                }
-                Add => {
-                    current = Add.apply(&current, operand);
+    /// Extend `left` by all following operators binding at least as tight as `min_precedence`.
+    fn climb<'a, I>(
+        mut left: Ast,
+        ops: &mut std::iter::Peekable<I>,
+        min_precedence: usize,
+        table: &OperatorTable,
+        total: usize,
+    ) -> Result<Ast>
+    where
+        I: Iterator<Item = &'a (Operator, Operand)>,
+    {
+        while let Some((op, operand)) = ops.next_if(|(op, _)| {
+            table
+                .lookup(op)
+                .map_or(true, |(precedence, _)| precedence >= min_precedence)
+        }) {
+            let (precedence, _) = table.lookup(op)?;
+            let mut right = operand.tree(table, total)?;
+            while let Some((next, _)) = ops.peek() {
+                let (next_precedence, next_assoc) = table.lookup(next)?;
+                if next_precedence > precedence {
+                    right = Self::climb(right, ops, precedence + 1, table, total)?;
+                } else if next_precedence == precedence && next_assoc == Associativity::Right {
+                    right = Self::climb(right, ops, precedence, table, total)?;
+                } else {
+                    break;
                 }
             }
+            let span = left.span().start..right.span().end;
+            left = Ast::Binary(op.clone(), Box::new(left), Box::new(right), span);
         }
+        Ok(left)
+    }
 
-        let last = current;
-
-        let mut current: Option<(Operand, Operator)> = None;
-
-        // second pass - apply everything (i.e. multiplication)
-        for (operand, op) in evaluate_later.into_iter() {
-            match current {
-                None => {
-                    current = Some((operand, op));
-                }
-                Some((old_operand, old_op)) => {
-                    let intermediate = old_op.apply(&old_operand, &operand);
-                    current = Some((intermediate, op));
-                }
-            }
-        }
-        if let Some((operand, op)) = current {
-            op.apply(&operand, &last).eval()
-        } else {
-            last.eval()
-        }
+    fn eval(&self, table: &OperatorTable) -> Result<i64> {
+        Ok(self.tree(table)?.eval()?)
     }
 }
 
 #[derive(Debug, Clone)]
-enum Operand {
+struct Operand {
+    kind: OperandKind,
+    // remaining input length before and after the operand, all that is known while parsing
+    from_end: (usize, usize),
+}
+
+#[derive(Debug, Clone)]
+enum OperandKind {
     Value(i64),
     Expr(Box<Expression>),
+    Neg(Box<Operand>),
 }
 
 impl Operand {
-    fn eval(&self) -> i64 {
-        match self {
-            Operand::Value(value) => *value,
-            Operand::Expr(expr) => expr.eval(),
-        }
+    fn tree(&self, table: &OperatorTable, total: usize) -> Result<Ast> {
+        let span = total - self.from_end.0..total - self.from_end.1;
+        Ok(match &self.kind {
+            OperandKind::Value(value) => Ast::Value(*value, span),
+            OperandKind::Expr(expr) => expr.tree_within(table, total)?.with_span(span),
+            OperandKind::Neg(inner) => Ast::Neg(Box::new(inner.tree(table, total)?), span),
+        })
     }
 
+    /// A number, a parenthesised expression or a negated operand.
     fn parse(i: &str) -> IResult<&str, Operand> {
-        use Operand::*;
-        let (i, num) = digit0(i)?;
-        if num.len() > 0 {
-            return Ok((i, Value(num.parse().unwrap())));
-        }
-        let (i, expr) = delimited(char('('), Expression::parse, char(')'))(i)?;
-        Ok((i, Expr(Box::new(expr))))
+        use OperandKind::*;
+        let start = i.len();
+        let (i, kind) = alt((
+            map_res(digit1, |num: &str| num.parse().map(Value)),
+            map_res(
+                delimited(char('('), Expression::parse, char(')')),
+                |expr| -> Result<_, ()> { Ok(Expr(Box::new(expr))) },
+            ),
+            map_res(
+                tuple((char('-'), space0, Operand::parse)),
+                |(_, _, inner)| -> Result<_, ()> { Ok(Neg(Box::new(inner))) },
+            ),
+        ))(i)?;
+        Ok((
+            i,
+            Self {
+                kind,
+                from_end: (start, i.len()),
+            },
+        ))
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 enum Operator {
     Add,
+    Sub,
     Mult,
+    Div,
+    Mod,
+    Pow,
 }
 
 impl Operator {
-    fn apply(&self, left: &Operand, right: &Operand) -> Operand {
-        let left_value = left.eval();
-        let right_value = right.eval();
+    /// Checked application, division and modulo truncate towards zero.
+    fn apply(&self, left: i64, right: i64) -> Result<i64, &'static str> {
+        use Operator::*;
         match self {
-            Operator::Add => Operand::Value(left_value + right_value),
-            Operator::Mult => Operand::Value(left_value * right_value),
+            Div | Mod if right == 0 => return Err("division by zero"),
+            Pow if right < 0 => return Err("negative exponent"),
+            _ => {}
         }
+        let result = match self {
+            Add => left.checked_add(right),
+            Sub => left.checked_sub(right),
+            Mult => left.checked_mul(right),
+            Div => left.checked_div(right),
+            Mod => left.checked_rem(right),
+            Pow => u32::try_from(right)
+                .ok()
+                .and_then(|exp| left.checked_pow(exp)),
+        };
+        result.ok_or("integer overflow")
     }
 
     fn parse(i: &str) -> IResult<&str, Operator> {
         use Operator::*;
-        alt((value(Add, char('+')), value(Mult, char('*'))))(i)
+        alt((
+            value(Add, char('+')),
+            value(Sub, char('-')),
+            value(Mult, char('*')),
+            value(Div, char('/')),
+            value(Mod, char('%')),
+            value(Pow, char('^')),
+        ))(i)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => '+',
+            Operator::Sub => '-',
+            Operator::Mult => '*',
+            Operator::Div => '/',
+            Operator::Mod => '%',
+            Operator::Pow => '^',
+        };
+        write!(f, "{}", symbol)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nom::character::complete::line_ending;
 
     #[test]
     fn test_parsing() -> Result<()> {
@@ -204,19 +599,151 @@ mod tests {
 
     #[test]
     fn test_eval() -> Result<()> {
-        assert_eq!(26, Expression::parse_full("2 * 3 + (4 * 5)")?.eval());
+        let table = OperatorTable::part1();
+        assert_eq!(26, Expression::parse_full("2 * 3 + (4 * 5)")?.eval(&table)?);
         assert_eq!(
             437,
-            Expression::parse_full("5 + (8 * 3 + 9 + 3 * 4 * 3)")?.eval()
+            Expression::parse_full("5 + (8 * 3 + 9 + 3 * 4 * 3)")?.eval(&table)?
         );
         assert_eq!(
             12240,
-            Expression::parse_full("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))")?.eval()
+            Expression::parse_full("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))")?.eval(&table)?
         );
         assert_eq!(
             13632,
-            Expression::parse_full("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2")?.eval()
+            Expression::parse_full("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2")?
+                .eval(&table)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_part2() -> Result<()> {
+        let table = OperatorTable::part2();
+        assert_eq!(46, Expression::parse_full("2 * 3 + (4 * 5)")?.eval(&table)?);
+        assert_eq!(
+            1445,
+            Expression::parse_full("5 + (8 * 3 + 9 + 3 * 4 * 3)")?.eval(&table)?
+        );
+        assert_eq!(
+            669060,
+            Expression::parse_full("5 * 9 * (7 * 3 * 3 + 9 * 3 + (8 + 6 * 4))")?.eval(&table)?
+        );
+        assert_eq!(
+            23340,
+            Expression::parse_full("((2 + 4 * 9) * (6 + 9 * 8 + 6) + 6) + 2 + 4 * 2")?
+                .eval(&table)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tables() -> Result<()> {
+        let expr = Expression::parse_full("1 + 2 * 3 + 4")?;
+        assert_eq!(expr.eval(&OperatorTable::standard())?, 11);
+        assert_eq!(
+            expr.tree(&OperatorTable::parse("+:right *:right")?)?
+                .to_string(),
+            "1 + (2 * (3 + 4))"
+        );
+        assert_eq!(
+            OperatorTable::parse("+ > *:right")?.to_string(),
+            "+ > *:right"
+        );
+        assert_eq!(OperatorTable::parse("part2")?, OperatorTable::part2());
+        assert!(OperatorTable::parse("+ > + *").is_err());
+        assert!(OperatorTable::parse("+ > ~").is_err());
+        assert!(expr.eval(&OperatorTable::parse("*")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_arithmetic() -> Result<()> {
+        let standard = OperatorTable::standard();
+        let tree = |line| Expression::parse_full(line)?.tree(&standard);
+        assert_eq!(tree("2 ^ 3 ^ 2")?.to_string(), "2 ^ (3 ^ 2)");
+        assert_eq!(tree("2 ^ 3 ^ 2")?.eval()?, 512);
+        assert_eq!(
+            tree("10 - 4 - 3 * -2")?.to_string(),
+            "(10 - 4) - (3 * (-2))"
+        );
+        assert_eq!(tree("10 - 4 - 3 * -2")?.eval()?, 12);
+        assert_eq!(tree("-(7 % 4) + -7 / 2")?.eval()?, -6);
+        assert_eq!(tree("- -3")?.eval()?, 3);
+        assert_eq!(tree("-2 ^ 2")?.to_string(), "(-2) ^ 2");
+        assert_eq!(tree("- -3")?.to_string(), "-(-3)");
+        assert_eq!(tree("-2 ^ 2")?.eval()?, 4);
+
+        let part1 = OperatorTable::part1();
+        assert_eq!(Expression::parse_full("10 - 4 * 2")?.eval(&part1)?, 12);
+        assert!(Expression::parse_full("99999999999999999999").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_checked() -> Result<()> {
+        let line = "1 + (3037000500 * 3037000500) * 2";
+        let err = Expression::parse_full(line)?
+            .tree(&OperatorTable::standard())?
+            .eval()
+            .unwrap_err();
+        assert_eq!(err.reason, "integer overflow");
+        assert_eq!(&line[err.span.clone()], "(3037000500 * 3037000500)");
+
+        let line = "(1 + 2) + 6 / (3 - 3)";
+        let err = Expression::parse_full(line)?
+            .tree(&OperatorTable::part2())?
+            .eval()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "division by zero in sub-expression at 0..21"
+        );
+
+        let err = Expression::parse_full("2 + 3 ^ -1")?
+            .tree(&OperatorTable::standard())?
+            .eval()
+            .unwrap_err();
+        assert_eq!((err.reason, err.span), ("negative exponent", 4..10));
+        assert!(Expression::parse_full("-(0 - 9223372036854775807 - 1)")?
+            .eval(&OperatorTable::standard())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_repl() -> Result<()> {
+        let mut repl = Repl::new(&[])?;
+        assert_eq!(
+            repl.handle("2 * 3 + 4 * 5")?,
+            [
+                "+ - * / % ^           | ((2 * 3) + 4) * 5 = 50",
+                "+ - > * / % ^         | (2 * (3 + 4)) * 5 = 70",
+                "^:right > * / % > + - | (2 * 3) + (4 * 5) = 26",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            repl.handle(":set part2; * / > + -")?,
+            "[1] + - > * / % ^\n[2] * / > + -"
+        );
+        assert_eq!(
+            repl.handle("1 + 3 / (2 - 2)")?,
+            [
+                "+ - > * / % ^ | (1 + 3) / (2 - 2) = division by zero in sub-expression at 0..15 (1 + 3 / (2 - 2))",
+                "* / > + -     | 1 + (3 / (2 - 2)) = division by zero in sub-expression at 4..15 (3 / (2 - 2))",
+            ]
+            .join("\n")
+        );
+        assert!(repl.handle(":set + > ~").is_err());
+        assert_eq!(
+            repl.handle(":set").unwrap_err().to_string(),
+            "Usage: :set <table>[; <table>...]"
         );
+        assert!(repl.handle(":add part1;").is_err());
+        assert_eq!(repl.handle(":tables")?, "[1] + - > * / % ^\n[2] * / > + -");
+        assert!(repl.handle(":unknown").is_err());
+        assert!(repl.handle("1 +").is_err());
         Ok(())
     }
 
@@ -233,10 +760,10 @@ mod tests {
 
     #[test]
     fn parse_input() -> Result<()> {
-        let input = read_to_string(&PathBuf::from("input.txt"))?;
+        let input = read_to_string(PathBuf::from("input.txt"))?;
 
         match tuple((Expression::parse, line_ending, Expression::parse))(&input) {
-            Ok((i, (first, _, second))) => {
+            Ok((_, (first, _, second))) => {
                 eprintln!("Parsed first: {:?}\nSecond: {:?}", first, second);
             }
             Err(e) => bail!("{}", e),
@@ -1,58 +1,68 @@
 use anyhow::{bail, Context, Result};
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, is_not, tag, take_while1},
-    character::complete::{alpha1, anychar, char, digit1, line_ending, none_of, one_of, space0},
-    combinator::{map_res, value},
-    multi::{many0, many1, separated_list1},
-    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    bytes::complete::tag,
+    character::complete::{alpha1, anychar, char, digit1, line_ending, space0},
+    combinator::map_res,
+    multi::{many1, separated_list1},
+    sequence::{delimited, terminated, tuple},
     Finish, IResult,
 };
-use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
-use std::fs::read_to_string;
+use std::fmt::Write;
+use std::fs::{read_to_string, write};
 use std::path::{Path, PathBuf};
 
+/// Replacement rules for part 2, introducing loops.
+const PART2_RULES: &str = "8: 42 | 42 8\n11: 42 31 | 42 11 31";
+
 fn main() -> Result<()> {
-    let input = PathBuf::from(
-        env::args()
-            .skip(1)
-            .next()
-            .with_context(|| "No input provided!")?,
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input = PathBuf::from(args.first().with_context(|| "No input provided!")?);
 
     let ruleset = RuleSet::read_from(&input)?;
-    println!("ruleset: {:#?}", ruleset);
 
-    // part1(&ruleset)?;
-    part2(ruleset)?;
+    match args.get(1).map(|s| s.as_str()) {
+        None => {
+            part1(&ruleset)?;
+            part2(ruleset)?;
+        }
+        Some("dfa") => {
+            let dfa = Compiler::new(&ruleset).compile(0)?;
+            let matching = ruleset.strings.iter().filter(|s| dfa.matches(s)).count();
+            println!(
+                "(dfa, {} states) num matching entries: {}",
+                dfa.len(),
+                matching
+            );
+        }
+        Some("regex") => {
+            let regex = Regex::new(&format!("^{}$", ruleset.to_regex(0)?))?;
+            let matching = ruleset.strings.iter().filter(|s| regex.is_match(s)).count();
+            println!("(regex) num matching entries: {}", matching);
+        }
+        Some("dot") => {
+            let output = args.get(2).with_context(|| "No output file provided!")?;
+            write(output, Compiler::new(&ruleset).compile(0)?.to_dot())?;
+        }
+        Some(other) => bail!("Unknown command: {}", other),
+    }
 
     Ok(())
 }
 
 fn part1(ruleset: &RuleSet) -> Result<()> {
-    let matching = ruleset.get_matching()?;
-    println!("================================================================================");
+    let matching = ruleset.get_matching(0)?;
     println!("(part1) num matching entries: {}", matching.len());
-    println!("================================================================================");
     Ok(())
 }
 
 fn part2(mut ruleset: RuleSet) -> Result<()> {
-    // ruleset.rules.insert(8, Rule::Multi(42));
-    // ruleset.rules.insert(11, Rule::SameN(42, 31));
-    ruleset.rules.insert(0, Rule::AtLeast(42, 31));
-    println!("{:#?}", ruleset);
-    let matching = ruleset.get_matching()?;
-    println!("================================================================================");
+    ruleset.patch(PART2_RULES)?;
+    let matching = ruleset.get_matching(0)?;
     println!("(part2) num matching entries: {}", matching.len());
-    println!("================================================================================");
-
-    /*
-     * for (i, entry) in matching.iter().enumerate() {
-     *     println!("#{}: {}", i, entry);
-     * }
-     */
     Ok(())
 }
 
@@ -60,9 +70,6 @@ fn part2(mut ruleset: RuleSet) -> Result<()> {
 enum Rule {
     Explicit(char),
     Alt(Vec<Vec<usize>>),
-    Multi(usize),
-    SameN(usize, usize),
-    AtLeast(usize, usize), // first pattern must be matched at least as often as the second pattern
 }
 
 impl Rule {
@@ -84,7 +91,7 @@ impl Rule {
 
     fn parse_explicit(i: &str) -> IResult<&str, Rule> {
         let (i, s) = delimited(char('"'), anychar, char('"'))(i)?;
-        Ok((i, Rule::Explicit(s.into())))
+        Ok((i, Rule::Explicit(s)))
     }
 }
 
@@ -112,11 +119,11 @@ impl RuleSet {
     }
 
     fn read_from(input: &Path) -> Result<Self> {
-        let input = read_to_string(&input)?;
+        let input = read_to_string(input)?;
         let rules = {
             match Self::parse(&input).finish() {
                 Ok((i, rules)) => {
-                    if i.len() > 0 {
+                    if !i.is_empty() {
                         bail!("Num bytes not consumed: {}", i.len());
                     } else {
                         rules
@@ -128,173 +135,554 @@ impl RuleSet {
         Ok(rules)
     }
 
-    fn match_rule<I: Iterator<Item = char> + Clone>(
-        &self,
-        idx: usize,
-        mut i: I,
-    ) -> Result<Option<I>> {
-        if i.clone().next().is_none() {
-            return Ok(None);
+    /// Replace or add rules given in the input format, one per line.
+    fn patch(&mut self, rules: &str) -> Result<()> {
+        match separated_list1(line_ending, Rule::parse)(rules.trim()).finish() {
+            Ok((i, patched)) => {
+                if !i.is_empty() {
+                    bail!("Num bytes not consumed in patch: {}", i.len());
+                }
+                self.rules.extend(patched);
+                Ok(())
+            }
+            Err(e) => bail!("Error parsing patch: {}", e),
         }
+    }
 
-        let rule = self
-            .rules
-            .get(&idx)
-            .with_context(|| format!("Invalid rule index: {}", idx))?;
+    fn get_matching(&self, start: usize) -> Result<Vec<String>> {
+        let grammar = Grammar::new(self)?;
+        grammar.check(start)?;
+        Ok(self
+            .strings
+            .iter()
+            .filter(|e| grammar.matches(start, e))
+            .cloned()
+            .collect())
+    }
+}
 
-        eprintln!(
-            "Trying rule #{} while matching {}",
-            idx,
-            i.clone().collect::<String>()
-        );
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Symbol {
+    Terminal(char),
+    Rule(usize),
+}
 
-        match rule {
-            Rule::Explicit(literal) => match i.next() {
-                Some(c) => {
-                    if *literal == c {
-                        return Ok(Some(i));
-                    } else {
-                        return Ok(None);
+/// Context-free grammar of a rule set, every production being non-empty.
+#[derive(Debug, Clone)]
+struct Grammar {
+    productions: HashMap<usize, Vec<Vec<Symbol>>>,
+}
+
+/// Production `alt` of `rule` with `dot` symbols matched, starting at position `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    alt: usize,
+    dot: usize,
+    origin: usize,
+}
+
+impl Grammar {
+    fn new(ruleset: &RuleSet) -> Result<Self> {
+        let productions: HashMap<_, _> = ruleset
+            .rules
+            .iter()
+            .map(|(idx, rule)| {
+                let alternatives = match rule {
+                    Rule::Explicit(c) => vec![vec![Symbol::Terminal(*c)]],
+                    Rule::Alt(alts) => alts
+                        .iter()
+                        .map(|seq| seq.iter().map(|r| Symbol::Rule(*r)).collect())
+                        .collect(),
+                };
+                (*idx, alternatives)
+            })
+            .collect();
+        for (idx, alternatives) in productions.iter() {
+            for symbol in alternatives.iter().flatten() {
+                if let Symbol::Rule(r) = symbol {
+                    if !productions.contains_key(r) {
+                        bail!("Rule #{} refers to invalid rule index: {}", idx, r);
                     }
                 }
-                None => return Ok(None),
-            },
-            Rule::Alt(vec) => {
-                'a: for rls in vec.iter() {
-                    let mut i = i.clone();
-                    for idx in rls.iter() {
-                        match self.match_rule(*idx, i)? {
-                            Some(i_matched) => {
-                                i = i_matched;
-                            }
-                            None => {
-                                continue 'a;
-                            }
+            }
+        }
+        Ok(Self { productions })
+    }
+
+    fn check(&self, start: usize) -> Result<()> {
+        if !self.productions.contains_key(&start) {
+            bail!("Invalid rule index: {}", start);
+        }
+        Ok(())
+    }
+
+    /// Earley recognizer, handles arbitrary (also left-) recursive rules.
+    fn matches(&self, start: usize, message: &str) -> bool {
+        let chars: Vec<char> = message.chars().collect();
+        let mut sets: Vec<Vec<Item>> = vec![Vec::new(); chars.len() + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); chars.len() + 1];
+
+        let mut add = |sets: &mut Vec<Vec<Item>>, pos: usize, item: Item| {
+            if seen[pos].insert(item) {
+                sets[pos].push(item);
+            }
+        };
+
+        for alt in 0..self.productions[&start].len() {
+            add(
+                &mut sets,
+                0,
+                Item {
+                    rule: start,
+                    alt,
+                    dot: 0,
+                    origin: 0,
+                },
+            );
+        }
+
+        for pos in 0..=chars.len() {
+            let mut k = 0;
+            while k < sets[pos].len() {
+                let item = sets[pos][k];
+                k += 1;
+                match self.productions[&item.rule][item.alt].get(item.dot) {
+                    Some(Symbol::Terminal(c)) => {
+                        if chars.get(pos) == Some(c) {
+                            add(
+                                &mut sets,
+                                pos + 1,
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            );
                         }
                     }
-                    return Ok(Some(i.clone()));
-                }
-                Ok(None)
-            }
-            Rule::Multi(idx) => {
-                // longest matching only
-                let mut times_matched = 0;
-                let mut i = i.clone();
-                loop {
-                    match self.match_rule(*idx, i.clone())? {
-                        Some(i_new) => {
-                            i = i_new;
-                            times_matched += 1;
+                    Some(Symbol::Rule(rule)) => {
+                        for alt in 0..self.productions[rule].len() {
+                            add(
+                                &mut sets,
+                                pos,
+                                Item {
+                                    rule: *rule,
+                                    alt,
+                                    dot: 0,
+                                    origin: pos,
+                                },
+                            );
                         }
-                        None => {
-                            break;
+                    }
+                    None => {
+                        // productions are non-empty, hence origin < pos and its set is final
+                        for w in 0..sets[item.origin].len() {
+                            let waiting = sets[item.origin][w];
+                            let next =
+                                self.productions[&waiting.rule][waiting.alt].get(waiting.dot);
+                            if next == Some(&Symbol::Rule(item.rule)) {
+                                add(
+                                    &mut sets,
+                                    pos,
+                                    Item {
+                                        dot: waiting.dot + 1,
+                                        ..waiting
+                                    },
+                                );
+                            }
                         }
                     }
                 }
-                if times_matched > 0 {
-                    // eprintln!("Matched multi-rule #{} {} times", idx, times_matched);
-                    Ok(Some(i))
-                } else {
-                    Ok(None)
-                }
             }
-            Rule::SameN(idx_fst, idx_snd) => {
-                // longest matching only for now
-                let mut i = i.clone();
-                let mut times_matched_fst = 0;
-                loop {
-                    match self.match_rule(*idx_fst, i.clone())? {
-                        Some(i_new) => {
-                            i = i_new;
-                            times_matched_fst += 1;
-                        }
-                        None => {
-                            break;
+        }
+
+        sets[chars.len()].iter().any(|item| {
+            item.rule == start
+                && item.origin == 0
+                && item.dot == self.productions[&start][item.alt].len()
+        })
+    }
+}
+
+impl RuleSet {
+    /// Regex pattern (without anchors) for a non-recursive rule.
+    fn to_regex(&self, idx: usize) -> Result<String> {
+        fn build(
+            rules: &HashMap<usize, Rule>,
+            idx: usize,
+            cache: &mut HashMap<usize, String>,
+            visiting: &mut HashSet<usize>,
+        ) -> Result<String> {
+            if let Some(pattern) = cache.get(&idx) {
+                return Ok(pattern.clone());
+            }
+            if !visiting.insert(idx) {
+                bail!("Rule #{} is recursive", idx);
+            }
+            let rule = rules
+                .get(&idx)
+                .with_context(|| format!("Invalid rule index: {}", idx))?;
+            let pattern = match rule {
+                Rule::Explicit(c) => regex::escape(&c.to_string()),
+                Rule::Alt(alts) => {
+                    let mut branches = Vec::new();
+                    for seq in alts.iter() {
+                        let mut branch = String::new();
+                        for r in seq.iter() {
+                            branch.push_str(&build(rules, *r, cache, visiting)?);
                         }
+                        branches.push(branch);
                     }
+                    format!("(?:{})", branches.join("|"))
                 }
-                let times_matched_fst = times_matched_fst;
-
-                if times_matched_fst == 0 {
-                    Ok(None)
-                } else {
-                    let mut times_matched_snd = 0;
-                    for _ in 0..times_matched_fst {
-                        match self.match_rule(*idx_snd, i.clone())? {
-                            Some(i_new) => {
-                                i = i_new;
-                                times_matched_snd += 1;
-                            }
-                            None => {
-                                break;
-                            }
-                        }
-                    }
-                    // eprintln!("Matched first same-rule #{} {} times. Matched second same-rule #{} {} times.", idx_fst, times_matched_fst, idx_snd, times_matched_snd);
+            };
+            visiting.remove(&idx);
+            cache.insert(idx, pattern.clone());
+            Ok(pattern)
+        }
+        build(&self.rules, idx, &mut HashMap::new(), &mut HashSet::new())
+    }
+}
 
-                    if times_matched_fst == times_matched_snd {
-                        Ok(Some(i))
-                    } else {
-                        Ok(None)
-                    }
+/// Partial DFA over a fixed alphabet, missing transitions lead to rejection.
+#[derive(Debug, Clone, PartialEq)]
+struct Dfa {
+    alphabet: Vec<char>,
+    // transitions[state][symbol], state 0 is the start state
+    transitions: Vec<Vec<Option<usize>>>,
+    accepting: Vec<bool>,
+}
+
+impl Dfa {
+    fn literal(alphabet: &[char], c: char) -> Self {
+        let mut transitions = vec![vec![None; alphabet.len()]; 2];
+        transitions[0][alphabet.iter().position(|a| *a == c).unwrap()] = Some(1);
+        Self {
+            alphabet: alphabet.to_vec(),
+            transitions,
+            accepting: vec![false, true],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Runs in time linear in the length of the message.
+    fn matches(&self, message: &str) -> bool {
+        let mut state = 0;
+        for c in message.chars() {
+            let next = match self.alphabet.iter().position(|a| *a == c) {
+                Some(symbol) => self.transitions[state][symbol],
+                None => None,
+            };
+            state = match next {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+        self.accepting[state]
+    }
+
+    /// Moore partition refinement, missing transitions act as a shared dead state.
+    fn minimize(&self) -> Self {
+        let mut class: Vec<usize> = self.accepting.iter().map(|a| *a as usize).collect();
+        let mut num_classes = 0;
+        loop {
+            let mut signatures = BTreeMap::new();
+            let refined: Vec<usize> = (0..self.len())
+                .map(|state| {
+                    let signature = (
+                        class[state],
+                        self.transitions[state]
+                            .iter()
+                            .map(|t| t.map(|t| class[t]))
+                            .collect::<Vec<_>>(),
+                    );
+                    let next_id = signatures.len();
+                    *signatures.entry(signature).or_insert(next_id)
+                })
+                .collect();
+            let done = signatures.len() == num_classes;
+            num_classes = signatures.len();
+            class = refined;
+            if done {
+                break;
+            }
+        }
+        // the start state is seen first and hence stays class 0
+        let mut transitions = vec![Vec::new(); num_classes];
+        let mut accepting = vec![false; num_classes];
+        for state in 0..self.len() {
+            transitions[class[state]] = self.transitions[state]
+                .iter()
+                .map(|t| t.map(|t| class[t]))
+                .collect();
+            accepting[class[state]] = self.accepting[state];
+        }
+        Self {
+            alphabet: self.alphabet.clone(),
+            transitions,
+            accepting,
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dfa {\n    rankdir=LR;\n    node [shape=circle];\n");
+        writeln!(dot, "    start [shape=point];\n    start -> 0;").unwrap();
+        for (state, transitions) in self.transitions.iter().enumerate() {
+            if self.accepting[state] {
+                writeln!(dot, "    {} [shape=doublecircle];", state).unwrap();
+            }
+            let mut labels: BTreeMap<usize, Vec<char>> = BTreeMap::new();
+            for (symbol, target) in transitions.iter().enumerate() {
+                if let Some(target) = target {
+                    labels
+                        .entry(*target)
+                        .or_default()
+                        .push(self.alphabet[symbol]);
                 }
             }
-            Rule::AtLeast(idx_fst, idx_snd) => {
-                // longest matching only for now
-                let mut i = i.clone();
-                let mut times_matched_fst = 0;
-                loop {
-                    match self.match_rule(*idx_fst, i.clone())? {
-                        Some(i_new) => {
-                            i = i_new;
-                            times_matched_fst += 1;
-                        }
-                        None => {
-                            break;
-                        }
-                    }
+            for (target, chars) in labels {
+                let label: Vec<String> = chars.iter().map(|c| c.to_string()).collect();
+                writeln!(
+                    dot,
+                    "    {} -> {} [label=\"{}\"];",
+                    state,
+                    target,
+                    label.join(",")
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// NFA with epsilon transitions, only used to combine DFAs.
+#[derive(Debug, Clone, Default)]
+struct Nfa {
+    edges: Vec<Vec<(usize, usize)>>,
+    epsilon: Vec<Vec<usize>>,
+    accepting: Vec<bool>,
+}
+
+impl Nfa {
+    /// Copy all states of `dfa`, returning the offset of its start state.
+    fn embed(&mut self, dfa: &Dfa) -> usize {
+        let offset = self.edges.len();
+        for (state, transitions) in dfa.transitions.iter().enumerate() {
+            self.edges.push(
+                transitions
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(symbol, t)| t.map(|t| (symbol, t + offset)))
+                    .collect(),
+            );
+            self.epsilon.push(Vec::new());
+            self.accepting.push(dfa.accepting[state]);
+        }
+        offset
+    }
+
+    fn closure(&self, states: impl IntoIterator<Item = usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        let mut stack: Vec<usize> = states.into_iter().collect();
+        while let Some(state) = stack.pop() {
+            if closure.insert(state) {
+                stack.extend(self.epsilon[state].iter().copied());
+            }
+        }
+        closure
+    }
+
+    /// Subset construction from `start`, only reachable subsets become states.
+    fn determinize(&self, start: usize, alphabet: &[char]) -> Dfa {
+        let start = self.closure(vec![start]);
+        let mut ids = HashMap::new();
+        ids.insert(start.clone(), 0);
+        let mut subsets = vec![start];
+        let mut transitions = Vec::new();
+        let mut accepting = Vec::new();
+        let mut current = 0;
+        while current < subsets.len() {
+            let subset = subsets[current].clone();
+            accepting.push(subset.iter().any(|s| self.accepting[*s]));
+            let mut row = vec![None; alphabet.len()];
+            for (symbol, entry) in row.iter_mut().enumerate() {
+                let targets = self.closure(subset.iter().flat_map(|s| {
+                    self.edges[*s]
+                        .iter()
+                        .filter(move |(on, _)| *on == symbol)
+                        .map(|(_, t)| *t)
+                }));
+                if targets.is_empty() {
+                    continue;
                 }
-                if times_matched_fst <= 1 {
-                    Ok(None)
-                } else {
-                    let times_matched_fst = times_matched_fst;
-
-                    let mut times_matched_snd = 0;
-                    for _ in 0..times_matched_fst {
-                        match self.match_rule(*idx_snd, i.clone())? {
-                            Some(i_new) => {
-                                i = i_new;
-                                times_matched_snd += 1;
-                            }
-                            None => {
-                                break;
-                            }
+                let next_id = subsets.len();
+                let id = *ids.entry(targets.clone()).or_insert_with(|| {
+                    subsets.push(targets);
+                    next_id
+                });
+                *entry = Some(id);
+            }
+            transitions.push(row);
+            current += 1;
+        }
+        Dfa {
+            alphabet: alphabet.to_vec(),
+            transitions,
+            accepting,
+        }
+    }
+}
+
+/// Compiles non-recursive rules bottom-up to minimal DFAs.
+struct Compiler<'a> {
+    ruleset: &'a RuleSet,
+    alphabet: Vec<char>,
+    cache: HashMap<usize, Dfa>,
+    visiting: HashSet<usize>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(ruleset: &'a RuleSet) -> Self {
+        let alphabet: BTreeSet<char> = ruleset
+            .rules
+            .values()
+            .filter_map(|rule| match rule {
+                Rule::Explicit(c) => Some(*c),
+                Rule::Alt(_) => None,
+            })
+            .collect();
+        Self {
+            ruleset,
+            alphabet: alphabet.into_iter().collect(),
+            cache: HashMap::new(),
+            visiting: HashSet::new(),
+        }
+    }
+
+    fn compile(&mut self, idx: usize) -> Result<Dfa> {
+        if let Some(dfa) = self.cache.get(&idx) {
+            return Ok(dfa.clone());
+        }
+        if !self.visiting.insert(idx) {
+            bail!("Rule #{} is recursive, cannot compile to a DFA", idx);
+        }
+        let rule = self
+            .ruleset
+            .rules
+            .get(&idx)
+            .with_context(|| format!("Invalid rule index: {}", idx))?;
+        let dfa = match rule {
+            Rule::Explicit(c) => Dfa::literal(&self.alphabet, *c),
+            Rule::Alt(alts) => {
+                let mut nfa = Nfa::default();
+                let start = nfa.embed(&Dfa {
+                    alphabet: self.alphabet.clone(),
+                    transitions: vec![vec![None; self.alphabet.len()]],
+                    accepting: vec![false],
+                });
+                for seq in alts.iter() {
+                    // chain the parts, connecting accepting states to the next start via epsilon
+                    let mut ends = vec![start];
+                    for r in seq.iter() {
+                        let part = self.compile(*r)?;
+                        let offset = nfa.embed(&part);
+                        for end in ends.iter() {
+                            nfa.epsilon[*end].push(offset);
+                        }
+                        ends = (0..part.len())
+                            .filter(|s| part.accepting[*s])
+                            .map(|s| s + offset)
+                            .collect();
+                        for s in offset..offset + part.len() {
+                            nfa.accepting[s] = false;
                         }
                     }
-
-                    // eprint!("Matched first AtLeast-rule #{} {} times. Matched second AtLeast-rule #{} {} times.", idx_fst, times_matched_fst, idx_snd, times_matched_snd);
-                    if times_matched_snd > 0 && times_matched_fst > times_matched_snd {
-                        // eprintln!();
-                        // eprintln!("Num bytes left: {}", i.clone().count());
-                        Ok(Some(i))
-                    } else {
-                        // eprintln!("..aborting.");
-                        Ok(None)
+                    for end in ends {
+                        nfa.accepting[end] = true;
                     }
                 }
+                nfa.determinize(start, &self.alphabet).minimize()
             }
+        };
+        self.visiting.remove(&idx);
+        self.cache.insert(idx, dfa.clone());
+        Ok(dfa)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching() -> Result<()> {
+        let ruleset = RuleSet::read_from(Path::new("debug.txt"))?;
+        assert_eq!(ruleset.get_matching(0)?, vec!["ababbb", "abbbab"]);
+
+        let mut ruleset = RuleSet::read_from(Path::new("debug_part2.txt"))?;
+        assert_eq!(ruleset.get_matching(0)?.len(), 3);
+        ruleset.patch(PART2_RULES)?;
+        assert_eq!(ruleset.get_matching(0)?.len(), 12);
+        assert_eq!(
+            RuleSet::read_from(Path::new("debug_part2_mod.txt"))?.get_matching(0)?,
+            ruleset.get_matching(0)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recursion() -> Result<()> {
+        let mut ruleset = RuleSet::read_from(Path::new("debug.txt"))?;
+        // left recursive and ambiguous: 0 matches one or more a, 1 matches a^n b^n
+        ruleset.patch("0: 0 4 | 4 | 1\n1: 4 5 | 4 1 5")?;
+        let grammar = Grammar::new(&ruleset)?;
+        for (message, matches) in [
+            ("a", true),
+            ("aaaa", true),
+            ("aabb", true),
+            ("aab", false),
+            ("abab", false),
+            ("", false),
+        ] {
+            assert_eq!(grammar.matches(0, message), matches, "{}", message);
         }
+        assert!(Compiler::new(&ruleset).compile(0).is_err());
+        assert!(ruleset.to_regex(1).is_err());
+        assert!(ruleset.patch("0: 4 9").is_ok());
+        assert!(ruleset.get_matching(0).is_err());
+        Ok(())
     }
 
-    fn get_matching(&self) -> Result<Vec<String>> {
-        let mut rv = Vec::new();
-        for e in self.strings.iter() {
-            if let Some(mut i) = self.match_rule(0, e.chars())? {
-                if let None = i.next() {
-                    rv.push(e.to_string());
-                }
+    #[test]
+    fn test_compiled() -> Result<()> {
+        for file in ["debug.txt", "debug_part2.txt"] {
+            let ruleset = RuleSet::read_from(Path::new(file))?;
+            let dfa = Compiler::new(&ruleset).compile(0)?;
+            let regex = Regex::new(&format!("^{}$", ruleset.to_regex(0)?))?;
+            let grammar = Grammar::new(&ruleset)?;
+            for message in ruleset.strings.iter() {
+                assert_eq!(dfa.matches(message), grammar.matches(0, message));
+                assert_eq!(regex.is_match(message), grammar.matches(0, message));
             }
         }
-        Ok(rv)
+
+        let mut ruleset = RuleSet::read_from(Path::new("debug.txt"))?;
+        // all two-letter words: start, after one letter, accepting
+        ruleset.patch("0: 2 | 3")?;
+        let dfa = Compiler::new(&ruleset).compile(0)?;
+        assert_eq!(dfa.len(), 3);
+        assert!(dfa.matches("ba") && !dfa.matches("b") && !dfa.matches("abc"));
+        assert_eq!(
+            dfa.to_dot(),
+            "digraph dfa {\n    rankdir=LR;\n    node [shape=circle];\n    \
+             start [shape=point];\n    start -> 0;\n    0 -> 1 [label=\"a,b\"];\n    \
+             1 -> 2 [label=\"a,b\"];\n    2 [shape=doublecircle];\n}\n"
+        );
+        Ok(())
     }
 }